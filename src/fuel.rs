@@ -0,0 +1,367 @@
+use crate::tour::haversine_km;
+use std::fmt;
+
+/// Best-scoring stations kept per section of a leg as candidate fuel stops.
+const SECTION_CANDIDATES: usize = 4;
+/// Kilometres per degree of latitude, and of longitude at the equator.
+const KM_PER_DEGREE: f64 = 111.195;
+/// Sections a tank range is split into along each leg, so some candidates always lie
+/// shortly before the point where the tank would run dry.
+const SECTIONS_PER_RANGE: f64 = 4.0;
+
+/// A station selling the requested fuel grade.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Station {
+    pub lat: f64,
+    pub lng: f64,
+    /// Price per litre, in CLP
+    pub price: f64,
+}
+
+/// How far the vehicle can drive, in km.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tank {
+    /// On a full tank
+    pub range_km: f64,
+    /// On the fuel in the tank at the start of the route
+    pub fuel_km: f64,
+}
+
+/// Refuelling stop added to a route.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuelStop {
+    /// Position in the route of the stop it follows; stops sharing a leg are in order
+    pub after: usize,
+    /// Index into the stations passed to `plan`
+    pub station: usize,
+    /// Distance the stop adds to the route
+    pub detour_km: f64,
+}
+
+/// What a vehicle costs to run, besides the distance it drives.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vehicle {
+    pub km_per_litre: f64,
+    /// CLP per hour paid to the driver
+    pub driver_per_hour: Option<f64>,
+}
+
+/// Estimated cost of a trip, in CLP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TripCost {
+    pub litres: f64,
+    pub fuel_clp: f64,
+    /// `None` without a driver cost per hour
+    pub driver_clp: Option<f64>,
+    pub total_clp: f64,
+}
+
+impl Vehicle {
+    /// Cost of driving `distance_km` in `hours` with fuel at `price` per litre.
+    pub fn trip_cost(&self, distance_km: f64, hours: f64, price: f64) -> TripCost {
+        let litres = distance_km / self.km_per_litre;
+        let fuel_clp = litres * price;
+        let driver_clp = self.driver_per_hour.map(|wage| wage * hours);
+        TripCost { litres, fuel_clp, driver_clp, total_clp: fuel_clp + driver_clp.unwrap_or(0.0) }
+    }
+}
+
+/// Mean price over `stations`, or `None` when there are none.
+pub fn average_price(stations: &[Station]) -> Option<f64> {
+    (!stations.is_empty()).then(|| stations.iter().map(|s| s.price).sum::<f64>() / stations.len() as f64)
+}
+
+/// Index of the station closest to the `(lat, lng)` point, or `None` when there are none.
+pub fn nearest(stations: &[Station], point: (f64, f64)) -> Option<usize> {
+    (0..stations.len()).min_by(|&a, &b| {
+        let d = |s: usize| km(point, (stations[s].lat, stations[s].lng));
+        d(a).total_cmp(&d(b))
+    })
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FuelError {
+    /// No reachable station keeps the tank from running dry past this position in the route.
+    Stranded(usize),
+}
+
+impl fmt::Display for FuelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FuelError::Stranded(at) => write!(f, "The tank runs dry after stop {} of the route.", at),
+        }
+    }
+}
+
+/// Somewhere the tank is full, or the start of the route.
+struct Fill {
+    leg: usize,
+    point: (f64, f64),
+    /// Index into the stations, `None` for the start
+    station: Option<usize>,
+    fuel_km: f64,
+}
+
+fn km(a: (f64, f64), b: (f64, f64)) -> f64 {
+    haversine_km(a.0, a.1, b.0, b.1)
+}
+
+/// Refuelling stops that keep the vehicle from running dry along `route`, a sequence of
+/// `(lat, lng)` points driven in order on straight-line distances. Every stop fills the
+/// tank; the stops minimise the summed score `price × (1 + detour / range)`, i.e. the
+/// price of a tankful plus the share of it the detour burns, so fewer, cheaper and closer
+/// stations win. Returns no stops when the fuel at the start covers the whole route.
+///
+/// Each leg offers the best-scoring stations of every quarter-range section as
+/// candidates, and a shortest path over them finds the stops.
+pub fn plan(route: &[(f64, f64)], stations: &[Station], tank: &Tank) -> Result<Vec<FuelStop>, FuelError> {
+    // along[k]: km driven from the start to route[k]
+    let mut along = vec![0.0; route.len()];
+    for k in 1..route.len() {
+        along[k] = along[k - 1] + km(route[k - 1], route[k]);
+    }
+    let total = along.last().copied().unwrap_or(0.0);
+    if total <= tank.fuel_km {
+        return Ok(Vec::new());
+    }
+
+    let mut fills = vec![Fill { leg: 0, point: route[0], station: None, fuel_km: tank.fuel_km }];
+    for leg in 0..route.len() - 1 {
+        fills.extend(candidates(route[leg], route[leg + 1], stations, tank.range_km).into_iter()
+            .map(|s| Fill { leg, point: (stations[s].lat, stations[s].lng), station: Some(s), fuel_km: tank.range_km }));
+    }
+
+    // Distance driven from fill `u` to fill `v`, and the detour `v` adds after `u`. The
+    // detours of several stops on one leg add up to the leg's whole detour.
+    let link = |u: &Fill, v: &Fill| {
+        let (to, next) = (v.point, route[v.leg + 1]);
+        let (distance, prev) = if u.leg == v.leg {
+            (km(u.point, to), u.point)
+        } else {
+            let leg_end = u.leg + 1;
+            (km(u.point, route[leg_end]) + along[v.leg] - along[leg_end] + km(route[v.leg], to), route[v.leg])
+        };
+        (distance, km(prev, to) + km(to, next) - km(prev, next))
+    };
+    // Distance from fill `u` to route[k], for k past its leg
+    let to_stop = |u: &Fill, k: usize| km(u.point, route[u.leg + 1]) + along[k] - along[u.leg + 1];
+
+    let mut score = vec![f64::INFINITY; fills.len()];
+    let mut from = vec![0; fills.len()];
+    score[0] = 0.0;
+    for v in 1..fills.len() {
+        let price = stations[fills[v].station.expect("only the start has no station")].price;
+        for u in (0..v).rev() {
+            let fill = &fills[u];
+            // Fills are ordered along the route, so earlier ones are even further away
+            if fill.leg < fills[v].leg && along[fills[v].leg] - along[fill.leg + 1] > tank.range_km.max(tank.fuel_km) {
+                break;
+            }
+            let (distance, detour) = link(fill, &fills[v]);
+            if !score[u].is_finite() || distance > fill.fuel_km {
+                continue;
+            }
+            let cost = score[u] + price * (1.0 + detour / tank.range_km);
+            if cost < score[v] {
+                score[v] = cost;
+                from[v] = u;
+            }
+        }
+    }
+
+    let last = route.len() - 1;
+    let finish = (0..fills.len())
+        .filter(|&u| score[u].is_finite() && to_stop(&fills[u], last) <= fills[u].fuel_km)
+        .min_by(|&a, &b| score[a].total_cmp(&score[b]));
+    let Some(mut v) = finish else {
+        let stranded = (0..fills.len())
+            .filter(|&u| score[u].is_finite())
+            .map(|u| (fills[u].leg + 1..=last)
+                .take_while(|&k| to_stop(&fills[u], k) <= fills[u].fuel_km)
+                .last()
+                .unwrap_or(fills[u].leg))
+            .max()
+            .unwrap_or(0);
+        return Err(FuelError::Stranded(stranded));
+    };
+    let mut stops = Vec::new();
+    while v != 0 {
+        let u = from[v];
+        stops.push(FuelStop {
+            after: fills[v].leg,
+            station: fills[v].station.expect("only the start has no station"),
+            detour_km: link(&fills[u], &fills[v]).1,
+        });
+        v = u;
+    }
+    stops.reverse();
+    Ok(stops)
+}
+
+/// Stations at most `max_detour_km` off `route`, a sequence of `(lat, lng)` points
+/// driven in order, as `(index into stations, detour in km)` in station order. The detour
+/// is the least extra distance of stopping at the station on any leg, so a detailed
+/// road geometry gives closer results than the stops alone.
+pub fn along_route(route: &[(f64, f64)], stations: &[Station], max_detour_km: f64) -> Vec<(usize, f64)> {
+    assert!(route.len() >= 2, "A route needs at least two points");
+    // A station `d` km of detour off a leg of length L lies within √(d·(d + 2L))/2 of the
+    // leg, so a box around the route rules most stations out cheaply
+    let longest = route.windows(2).map(|w| km(w[0], w[1])).fold(0.0, f64::max);
+    let margin_km = (max_detour_km * (max_detour_km + 2.0 * longest)).sqrt() / 2.0;
+    let (mut south, mut north, mut west, mut east) = (f64::MAX, f64::MIN, f64::MAX, f64::MIN);
+    for &(lat, lng) in route {
+        (south, north, west, east) = (south.min(lat), north.max(lat), west.min(lng), east.max(lng));
+    }
+    let lat_margin = margin_km / KM_PER_DEGREE;
+    let widest = (south.abs().max(north.abs()) + lat_margin).min(89.0);
+    let lng_margin = margin_km / (KM_PER_DEGREE * widest.to_radians().cos());
+
+    stations.iter().enumerate()
+        .filter(|(_, s)| {
+            s.lat >= south - lat_margin && s.lat <= north + lat_margin
+                && s.lng >= west - lng_margin && s.lng <= east + lng_margin
+        })
+        .filter_map(|(i, s)| {
+            let point = (s.lat, s.lng);
+            let detour = route.windows(2)
+                .map(|w| km(w[0], point) + km(point, w[1]) - km(w[0], w[1]))
+                .fold(f64::INFINITY, f64::min)
+                .max(0.0);
+            (detour <= max_detour_km).then_some((i, detour))
+        })
+        .collect()
+}
+
+/// Candidate stations for the leg from `a` to `b`, ordered along it.
+fn candidates(a: (f64, f64), b: (f64, f64), stations: &[Station], range_km: f64) -> Vec<usize> {
+    let length = km(a, b);
+    let sections = ((length * SECTIONS_PER_RANGE / range_km).ceil() as usize).max(1);
+    let mut best: Vec<Vec<(f64, f64, usize)>> = vec![Vec::new(); sections];
+    for (s, station) in stations.iter().enumerate() {
+        let point = (station.lat, station.lng);
+        let (from_a, to_b) = (km(a, point), km(point, b));
+        let progress = if from_a + to_b > 0.0 { from_a / (from_a + to_b) } else { 0.0 };
+        let score = station.price * (1.0 + (from_a + to_b - length) / range_km);
+        best[((progress * sections as f64) as usize).min(sections - 1)].push((progress, score, s));
+    }
+    let mut chosen: Vec<(f64, f64, usize)> = best.into_iter()
+        .flat_map(|mut section| {
+            section.sort_by(|x, y| x.1.total_cmp(&y.1));
+            section.truncate(SECTION_CANDIDATES);
+            section
+        })
+        .collect();
+    chosen.sort_by(|x, y| x.0.total_cmp(&y.0));
+    chosen.into_iter().map(|(_, _, s)| s).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Point `x_km` east of (0, 0) along the equator.
+    fn east(x_km: f64) -> (f64, f64) {
+        (0.0, x_km / KM_PER_DEGREE)
+    }
+
+    fn station(x_km: f64, y_km: f64, price: f64) -> Station {
+        Station { lat: y_km / KM_PER_DEGREE, lng: x_km / KM_PER_DEGREE, price }
+    }
+
+    // ── Trip Cost Tests ───────────────────────────────────────────────────────
+
+    #[test]
+    fn trip_cost_should_add_fuel_and_driver_time() {
+        let vehicle = Vehicle { km_per_litre: 12.5, driver_per_hour: Some(6000.0) };
+        let cost = vehicle.trip_cost(250.0, 3.5, 1300.0);
+        assert_eq!(cost, TripCost { litres: 20.0, fuel_clp: 26_000.0, driver_clp: Some(21_000.0), total_clp: 47_000.0 });
+        let no_driver = Vehicle { driver_per_hour: None, ..vehicle };
+        assert_eq!(no_driver.trip_cost(250.0, 3.5, 1300.0).total_clp, 26_000.0);
+    }
+
+    #[test]
+    fn average_and_nearest_should_pick_the_price() {
+        let stations = [station(0.0, 10.0, 1200.0), station(100.0, 0.0, 1300.0), station(3.0, 0.0, 1250.0)];
+        assert_eq!(average_price(&stations), Some(1250.0));
+        assert_eq!(nearest(&stations, east(0.0)), Some(2));
+        assert_eq!((average_price(&[]), nearest(&[], east(0.0))), (None, None));
+    }
+
+    // ── Along Route Tests ─────────────────────────────────────────────────────
+
+    #[test]
+    fn along_route_should_measure_the_detour_off_the_nearest_leg() {
+        let route = [east(0.0), east(100.0), (100.0 / KM_PER_DEGREE, 100.0 / KM_PER_DEGREE)];
+        let stations = [
+            station(50.0, 0.0, 1000.0),   // on the first leg
+            station(50.0, 1.0, 1000.0),   // just off it
+            station(103.0, 50.0, 1000.0), // a little further off the second leg
+            station(50.0, 40.0, 1000.0),  // far from both
+        ];
+        let found = along_route(&route, &stations, 2.0);
+        assert_eq!(found.iter().map(|&(i, _)| i).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert!(found[0].1 < 1e-6);
+        assert!(found[1].1 > 0.0 && found[1].1 < found[2].1, "{:?}", found);
+    }
+
+    #[test]
+    fn along_route_should_keep_stations_off_long_legs() {
+        // 3 km off the middle of a 400 km leg adds only a few metres
+        let found = along_route(&[east(0.0), east(400.0)], &[station(200.0, 3.0, 1000.0)], 0.5);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].1 < 0.1);
+    }
+
+    // ── Refuelling Plan Tests ─────────────────────────────────────────────────
+
+    #[test]
+    fn plan_should_add_nothing_when_the_fuel_lasts() {
+        let tank = Tank { range_km: 500.0, fuel_km: 250.0 };
+        let stops = plan(&[east(0.0), east(100.0), east(200.0)], &[station(50.0, 0.0, 1000.0)], &tank);
+        assert_eq!(stops, Ok(Vec::new()));
+    }
+
+    #[test]
+    fn plan_should_refuel_before_the_tank_runs_dry() {
+        let tank = Tank { range_km: 300.0, fuel_km: 150.0 };
+        let stations = [station(100.0, 0.0, 1000.0), station(250.0, 0.0, 1000.0)];
+        let stops = plan(&[east(0.0), east(200.0), east(400.0)], &stations, &tank).unwrap();
+        assert_eq!(stops.len(), 1);
+        assert_eq!((stops[0].after, stops[0].station), (0, 0));
+        assert!(stops[0].detour_km.abs() < 1e-6);
+    }
+
+    #[test]
+    fn plan_should_weigh_price_against_detour() {
+        let tank = Tank { range_km: 300.0, fuel_km: 100.0 };
+        let route = [east(0.0), east(200.0)];
+        // Slightly cheaper but a long way off the road, or a bit cheaper and close to it
+        let far = [station(50.0, 0.0, 1000.0), station(50.0, 60.0, 990.0)];
+        assert_eq!(plan(&route, &far, &tank).unwrap()[0].station, 0);
+        let near = [station(50.0, 0.0, 1000.0), station(50.0, 2.0, 990.0)];
+        assert_eq!(plan(&route, &near, &tank).unwrap()[0].station, 1);
+    }
+
+    #[test]
+    fn plan_should_chain_stops_on_a_long_leg() {
+        let tank = Tank { range_km: 100.0, fuel_km: 100.0 };
+        let stations: Vec<Station> = (1..10).map(|k| station(k as f64 * 40.0, 0.0, 1000.0)).collect();
+        let stops = plan(&[east(0.0), east(380.0)], &stations, &tank).unwrap();
+        let mut reached = 0.0;
+        for stop in &stops {
+            let x = stations[stop.station].lng * KM_PER_DEGREE;
+            assert!(x - reached <= 100.0 + 1e-6, "ran dry between {} and {}", reached, x);
+            reached = x;
+        }
+        assert!(380.0 - reached <= 100.0 + 1e-6);
+        assert_eq!(stops.len(), 4);
+    }
+
+    #[test]
+    fn plan_should_report_where_the_tank_runs_dry() {
+        let tank = Tank { range_km: 150.0, fuel_km: 150.0 };
+        let route = [east(0.0), east(100.0), east(200.0), east(300.0), east(400.0)];
+        let stops = plan(&route, &[station(120.0, 0.0, 1000.0)], &tank);
+        assert_eq!(stops, Err(FuelError::Stranded(2)));
+    }
+}
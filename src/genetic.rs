@@ -0,0 +1,210 @@
+use crate::tour::{Tour, EPS};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// Members drawn per tournament when choosing a parent.
+const TOURNAMENT_SIZE: usize = 3;
+
+/// Settings for `evolve`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Genetic {
+    /// Routes kept alive at once, including the starting route.
+    pub population: usize,
+    /// Children bred, one per generation.
+    pub generations: usize,
+    /// Probability that a child gets a double-bridge kick before its local search.
+    pub mutation_rate: f64,
+}
+
+impl Default for Genetic {
+    fn default() -> Self {
+        Self { population: 20, generations: 500, mutation_rate: 0.2 }
+    }
+}
+
+/// A route in the population: a cycle as returned by `Tour::order`, and its cost.
+#[derive(Debug, Clone)]
+struct Individual {
+    order: Vec<usize>,
+    cost: f64,
+}
+
+/// Memetic genetic algorithm: a population of locally optimal routes is bred with order
+/// crossover (OX), each child is optionally mutated by a double bridge and then improved
+/// with 2-opt and Or-opt, and it replaces the worst member when it beats it.
+///
+/// The current route seeds the population, so the result is never worse than the route
+/// it started from. Randomness comes from the tour's RNG, and breeding stops after
+/// `generations` children or when the tour's budget runs out. Much slower than the other
+/// phases; meant for offline planning where quality matters more than latency.
+pub fn evolve(tour: &mut Tour, settings: &Genetic) {
+    tour.distance_matrix();
+    let start = tour.order();
+    // OX and the double bridge need a few stops to cut between
+    if start.len() < 5 || settings.population < 2 {
+        return;
+    }
+    let mut population = vec![Individual { cost: tour.order_cost(&start), order: start.clone() }];
+    for _ in 0..2 * settings.population {
+        if population.len() == settings.population || tour.budget().exhausted() {
+            break;
+        }
+        let mut order = start.clone();
+        order[1..].shuffle(tour.rng());
+        let child = local_search(tour, order);
+        if is_new(&population, &child) {
+            population.push(child);
+        }
+    }
+    if population.len() < 2 {
+        return;
+    }
+
+    let mutation_rate = settings.mutation_rate.clamp(0.0, 1.0);
+    for _ in 0..settings.generations {
+        if tour.budget().exhausted() {
+            break;
+        }
+        let a = tournament(&population, tour.rng());
+        let b = tournament(&population, tour.rng());
+        let mut order = order_crossover(&population[a].order, &population[b].order, tour.rng());
+        if tour.rng().gen_bool(mutation_rate) {
+            order = tour.double_bridge(&order);
+        }
+        let child = local_search(tour, order);
+        let worst = (0..population.len())
+            .max_by(|&i, &j| population[i].cost.total_cmp(&population[j].cost))
+            .expect("population is not empty");
+        if child.cost < population[worst].cost - EPS && is_new(&population, &child) {
+            population[worst] = child;
+        }
+    }
+
+    let best = population.into_iter()
+        .min_by(|a, b| a.cost.total_cmp(&b.cost))
+        .expect("population is not empty");
+    if best.cost < tour.order_cost(&tour.order()) - EPS {
+        tour.set_order(best.order);
+    }
+    tour.calculate_cost();
+}
+
+/// Improves `order` with 2-opt and Or-opt on a copy of `tour`, which shares its distances.
+fn local_search(tour: &Tour, order: Vec<usize>) -> Individual {
+    let mut copy = tour.clone();
+    copy.set_order(order);
+    copy.two_opt();
+    copy.or_opt();
+    let order = copy.order();
+    Individual { cost: copy.order_cost(&order), order }
+}
+
+/// Rejects children whose cost matches a member's, which is almost always the same route.
+/// Keeps the population from collapsing onto copies of its best member.
+fn is_new(population: &[Individual], child: &Individual) -> bool {
+    population.iter().all(|member| (member.cost - child.cost).abs() > EPS)
+}
+
+/// Index of the cheapest of `TOURNAMENT_SIZE` members drawn at random.
+fn tournament(population: &[Individual], rng: &mut impl Rng) -> usize {
+    (0..TOURNAMENT_SIZE)
+        .map(|_| rng.gen_range(0..population.len()))
+        .min_by(|&i, &j| population[i].cost.total_cmp(&population[j].cost))
+        .expect("tournament draws at least one member")
+}
+
+/// Order crossover on cycles that start at the depot: a random slice of `a` is kept in
+/// place and the remaining positions, from the end of the slice around to its start, are
+/// filled with the missing stops in the order they follow the slice in `b`.
+fn order_crossover(a: &[usize], b: &[usize], rng: &mut impl Rng) -> Vec<usize> {
+    let m = a.len();
+    let mut cuts = [rng.gen_range(1..m), rng.gen_range(1..m)];
+    cuts.sort_unstable();
+    let (lo, hi) = (cuts[0], cuts[1] + 1);
+
+    let size = a.iter().chain(b).max().map_or(0, |&id| id + 1);
+    let mut taken = vec![false; size];
+    taken[a[0]] = true;
+    for &id in &a[lo..hi] {
+        taken[id] = true;
+    }
+    let mut child = a.to_vec();
+    let mut fill = (hi..m).chain(1..lo);
+    for &id in b[hi..].iter().chain(&b[1..hi]) {
+        if !taken[id] {
+            taken[id] = true;
+            child[fill.next().expect("one free position per missing stop")] = id;
+        }
+    }
+    child
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tour::{Budget, Strategy};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::time::Instant;
+
+    // ── Crossover Tests ───────────────────────────────────────────────────────
+
+    #[test]
+    fn order_crossover_should_keep_every_stop_once() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let a: Vec<usize> = (0..12).collect();
+        let mut b = a.clone();
+        for _ in 0..50 {
+            b[1..].shuffle(&mut rng);
+            let mut child = order_crossover(&a, &b, &mut rng);
+            assert_eq!(child[0], 0, "depot stays first");
+            child.sort_unstable();
+            assert_eq!(child, a);
+        }
+    }
+
+    #[test]
+    fn order_crossover_of_identical_parents_should_copy_them() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let parent = vec![0, 4, 2, 7, 1, 6, 3, 5];
+        for _ in 0..20 {
+            assert_eq!(order_crossover(&parent, &parent, &mut rng), parent);
+        }
+    }
+
+    // ── Evolution Tests ───────────────────────────────────────────────────────
+
+    #[test]
+    fn evolve_should_never_worsen_the_route() {
+        for seed in 0..3 {
+            let mut tour = Tour::create_random_nodes_with_seed(40, 100.0, 100.0, seed);
+            tour.solve(&Strategy::named("fast").unwrap());
+            let before = tour.cost;
+            evolve(&mut tour, &Genetic { population: 8, generations: 40, mutation_rate: 0.2 });
+            assert!(tour.cost <= before + 1e-3, "seed {}: {} > {}", seed, tour.cost, before);
+            assert_eq!(tour.route.len(), 41);
+        }
+    }
+
+    #[test]
+    fn evolve_should_be_reproducible_for_a_seed() {
+        let settings = Genetic { population: 6, generations: 30, mutation_rate: 0.5 };
+        let run = || {
+            let mut tour = Tour::create_random_nodes_with_seed(30, 100.0, 100.0, 9);
+            tour.nearest_neighbour_tour();
+            evolve(&mut tour, &settings);
+            tour.route.iter().map(|n| n.id).collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn evolve_should_stop_when_the_budget_is_exhausted() {
+        let mut tour = Tour::create_random_nodes_with_seed(60, 100.0, 100.0, 4);
+        tour.nearest_neighbour_tour();
+        let route: Vec<usize> = tour.route.iter().map(|n| n.id).collect();
+        tour.set_budget(Budget::until(Instant::now()));
+        evolve(&mut tour, &Genetic::default());
+        assert_eq!(tour.route.iter().map(|n| n.id).collect::<Vec<_>>(), route);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::tour::{Tour, EPS};
+
+/// Rounds of member re-selection followed by re-ordering.
+const MAX_ROUNDS: usize = 20;
+
+/// Generalized TSP: stops sharing a `cluster` id are alternatives, and exactly one of them
+/// stays on `tour`'s route (e.g. any fuel station in an area); stops without a cluster are
+/// always visited. Returns the node ids left off the route, in id order.
+///
+/// Starts from the current route, so solve it first. Every cluster's cheapest member is
+/// inserted at its cheapest place, in the order the clusters come up on the route; then
+/// rounds of swapping each cluster's member for a cheaper one, anywhere on the route,
+/// alternate with 2-opt and Or-opt. A fixed endpoint settles its own cluster. Stops
+/// improving when the tour's budget runs out.
+pub fn choose(tour: &mut Tour, cluster: &[Option<usize>]) -> Vec<usize> {
+    assert_eq!(cluster.len(), tour.nodes.len(), "Clusters must have one entry per node");
+    let (start, end) = tour.endpoints();
+    let mut members = vec![Vec::new(); cluster.iter().flatten().max().map_or(0, |&c| c + 1)];
+    for (id, c) in cluster.iter().enumerate() {
+        if let Some(c) = *c {
+            members[c].push(id);
+        }
+    }
+    let fixed: Vec<usize> = start.into_iter().chain(end).collect();
+    for &e in &fixed {
+        if let Some(c) = cluster[e] {
+            assert!(fixed.iter().all(|&f| f == e || cluster[f] != Some(c)),
+                "Fixed endpoints must be in different clusters");
+            members[c] = vec![e];
+        }
+    }
+    let open = |c: usize| members[c].len() > 1;
+
+    // Clustered stops leave the route, then clusters with a choice come back one member each
+    let route: Vec<usize> = tour.route.iter().map(|n| n.id).collect();
+    let mut order = Vec::new();
+    let mut seen = vec![false; members.len()];
+    for &id in &route {
+        if let Some(c) = cluster[id].filter(|&c| open(c) && !seen[c]) {
+            seen[c] = true;
+            order.push(c);
+        }
+    }
+    let path = route.into_iter().filter(|&id| cluster[id].is_none_or(|c| members[c] == [id])).collect();
+    let mut choice = Choice { tour, free_start: start.is_none(), free_end: end.is_none(), path };
+    for &c in &order {
+        let (_, at, id) = choice.cheapest(&members[c]);
+        choice.path.insert(at, id);
+    }
+
+    for _ in 0..MAX_ROUNDS {
+        if choice.tour.budget().exhausted() {
+            break;
+        }
+        let mut improved = false;
+        for &c in &order {
+            improved |= choice.reselect(&members[c]);
+        }
+        if let Some(path) = choice.tour.reorder_path(&choice.path) {
+            choice.path = path;
+            improved = true;
+        }
+        if !improved {
+            break;
+        }
+    }
+    let path = choice.path;
+
+    let mut on_route = vec![false; tour.nodes.len()];
+    for &id in &path {
+        on_route[id] = true;
+    }
+    tour.route = path.iter().map(|&id| tour.nodes[id].clone()).collect();
+    tour.calculate_cost();
+    (0..tour.nodes.len()).filter(|&id| !on_route[id]).collect()
+}
+
+/// Working state: `path` is the route as in `Tour::route`, endpoints included.
+struct Choice<'a> {
+    tour: &'a Tour,
+    /// The first stop is not fixed, so stops can be removed from or added before it.
+    free_start: bool,
+    /// The last stop is not fixed, so stops can be removed from or added after it.
+    free_end: bool,
+    path: Vec<usize>,
+}
+
+impl Choice<'_> {
+    fn c(&self, from: usize, to: usize) -> f64 {
+        self.tour.travel_cost(from, to) as f64
+    }
+
+    /// Cost of linking `id` in between `prev` and `next`, either of which may be missing.
+    fn link(&self, prev: Option<usize>, id: usize, next: Option<usize>) -> f64 {
+        match (prev, next) {
+            (Some(prev), Some(next)) => self.c(prev, id) + self.c(id, next) - self.c(prev, next),
+            (Some(prev), None) => self.c(prev, id),
+            (None, Some(next)) => self.c(id, next),
+            (None, None) => 0.0,
+        }
+    }
+
+    /// Cost saved by removing the stop at position `i`.
+    fn saving(&self, i: usize) -> f64 {
+        let prev = i.checked_sub(1).map(|p| self.path[p]);
+        self.link(prev, self.path[i], self.path.get(i + 1).copied())
+    }
+
+    /// Cheapest member of `members` to insert, as `(added cost, position, id)`. The
+    /// fixed endpoints keep their places.
+    fn cheapest(&self, members: &[usize]) -> (f64, usize, usize) {
+        let first = usize::from(!self.free_start);
+        let last = if self.free_end { self.path.len() } else { self.path.len() - 1 };
+        members.iter()
+            .flat_map(|&id| (first..=last).map(move |at| (at, id)))
+            .map(|(at, id)| {
+                let prev = at.checked_sub(1).map(|p| self.path[p]);
+                (self.link(prev, id, self.path.get(at).copied()), at, id)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .expect("a cluster has members")
+    }
+
+    /// Swaps the member of a cluster on the route for the cheapest one to have instead,
+    /// possibly the same stop somewhere else. Returns whether the route got cheaper.
+    fn reselect(&mut self, members: &[usize]) -> bool {
+        let i = self.path.iter().position(|id| members.contains(id)).expect("one member is on the route");
+        let saving = self.saving(i);
+        let current = self.path.remove(i);
+        let (added, at, id) = self.cheapest(members);
+        if added < saving - EPS {
+            self.path.insert(at, id);
+            true
+        } else {
+            self.path.insert(i, current);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tour::Strategy;
+
+    /// Tour over stops at `points`, costing the straight-line distance between them.
+    fn plane_tour(points: &[(f32, f32)]) -> Tour {
+        let matrix = points.iter()
+            .map(|a| points.iter().map(|b| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()).collect())
+            .collect();
+        Tour::with_matrix(points.to_vec(), matrix).unwrap()
+    }
+
+    fn solved(mut tour: Tour) -> Tour {
+        tour.solve(&Strategy::named("fast").unwrap());
+        tour
+    }
+
+    #[test]
+    fn choose_should_keep_every_stop_without_clusters() {
+        let mut tour = solved(plane_tour(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]));
+        let cost = tour.cost;
+        assert!(choose(&mut tour, &[None; 4]).is_empty());
+        assert_eq!(tour.route.len(), 5);
+        assert_eq!(tour.cost, cost);
+    }
+
+    #[test]
+    fn choose_should_pick_the_member_on_the_way() {
+        // Stations 3 and 4 are alternatives; 3 lies between the stops, 4 far off
+        let mut tour = solved(plane_tour(&[(0.0, 0.0), (4.0, 0.0), (4.0, 2.0), (2.0, 0.1), (2.0, 9.0)]));
+        let skipped = choose(&mut tour, &[None, None, None, Some(0), Some(0)]);
+        assert_eq!(skipped, vec![4]);
+        assert_eq!(tour.route.len(), 5);
+        assert!(tour.route.iter().any(|n| n.id == 3));
+    }
+
+    #[test]
+    fn choose_should_visit_exactly_one_member_per_cluster() {
+        let mut tour = solved(Tour::create_random_nodes_with_seed(60, 100.0, 100.0, 3));
+        let cluster: Vec<Option<usize>> = (0..60).map(|id| (id >= 20).then_some(id % 8)).collect();
+        let skipped = choose(&mut tour, &cluster);
+        let visited: Vec<usize> = tour.route[..tour.route.len() - 1].iter().map(|n| n.id).collect();
+        assert_eq!(visited.len() + skipped.len(), 60);
+        assert!((0..20).all(|id| visited.contains(&id)), "stops without a cluster stay");
+        for c in 0..8 {
+            assert_eq!(visited.iter().filter(|&&id| cluster[id] == Some(c)).count(), 1, "cluster {}", c);
+        }
+        assert_eq!((tour.route[0].id, tour.route.last().unwrap().id), (0, 0));
+    }
+
+    #[test]
+    fn choose_should_beat_any_fixed_member() {
+        // Every single-member route through the clusters costs at least the chosen one
+        let points = [(0.0, 0.0), (10.0, 0.0), (5.0, 5.0), (5.0, -1.0), (12.0, 3.0), (-3.0, 4.0), (6.0, 6.0)];
+        let cluster = [None, None, Some(0), Some(0), Some(1), Some(1), Some(1)];
+        let mut tour = solved(plane_tour(&points));
+        choose(&mut tour, &cluster);
+        for a in [2, 3] {
+            for b in [4, 5, 6] {
+                let ids = [0, 1, a, b];
+                let mut fixed = solved(plane_tour(&points).subtour(&ids));
+                fixed.solve_exact();
+                assert!(tour.cost <= fixed.cost + 1e-4, "{} > {} with {} and {}", tour.cost, fixed.cost, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn choose_should_settle_a_cluster_on_its_fixed_endpoint() {
+        let mut tour = plane_tour(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (9.0, 0.0), (3.0, 0.0)]);
+        tour.set_endpoints(Some(0), Some(3));
+        tour.solve(&Strategy::named("fast").unwrap());
+        let skipped = choose(&mut tour, &[None, None, Some(0), Some(1), Some(1)]);
+        assert_eq!(skipped, vec![4]);
+        let route: Vec<usize> = tour.route.iter().map(|n| n.id).collect();
+        assert_eq!(route, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn choose_should_handle_free_endpoints() {
+        let mut tour = plane_tour(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (-5.0, 0.0), (3.0, 0.0)]);
+        tour.set_endpoints(None, None);
+        tour.solve(&Strategy::named("fast").unwrap());
+        let skipped = choose(&mut tour, &[None, None, None, Some(0), Some(0)]);
+        assert_eq!(skipped, vec![3]);
+        assert_eq!(tour.cost, 3.0);
+    }
+}
@@ -1,17 +1,34 @@
 use axum::{extract::{Json, Query, State}, http::StatusCode, response::IntoResponse};
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
-use crate::tour::Tour;
+use crate::tour::{haversine_km, Budget, Construction, OrderConstraints, Strategy, TimeWindow, Tour};
+use crate::fuel::{self, FuelError, FuelStop, Station, Tank, Vehicle};
+use crate::gtsp;
+use crate::orienteering::{self, OrienteeringError, Selection};
+use crate::vrp::{self, Fleet, VehicleRoute, VrpError};
 
 // ── Constants ──────────────────────────────────────────────────────────────────
 
-const MAX_NODES: usize = 200;
+const MAX_NODES: usize = 2000;
+const MAX_VEHICLES: usize = 50;
+const MAX_ITERATIONS: usize = 10_000_000;
+const MAX_STARTS: usize = 64;
+const DEFAULT_THREADS: usize = 1; // per request unless asked; never more than the server's cores
+const EXACT_MAX_NODES: usize = 20; // solved to proven optimality after the heuristic
 const SOLVER_TIMEOUT_SECS: u64 = 30;
+const SOLVER_GRACE_SECS: u64 = 5; // time for the solver to notice its budget ran out
 const OSRM_TIMEOUT_SECS: u64 = 10;
 const OSRM_MAX_COORDS: usize = 100; // public demo server limit
 const OSRM_BASE: &str = "http://router.project-osrm.org/table/v1/driving";
+const PRICE_RANGE_CLP: std::ops::RangeInclusive<f64> = 100.0..=5000.0; // plausible CNE price per litre
+const ASSUMED_SPEED_KMH: f64 = 40.0; // driving time for trip costs and detours without OSRM road times
+const MAX_GEOMETRY_POINTS: usize = 50_000;
+const MAX_DETOUR_KM: f64 = 50.0;
+const DEFAULT_DETOUR_KM: f64 = 2.0;
+const DEFAULT_ROUTE_STATIONS: usize = 20;
 
 // ── Validation ────────────────────────────────────────────────────────────────
 
@@ -21,6 +38,24 @@ fn is_valid_coord(lat: f64, lng: f64) -> bool {
         && (-180.0..=180.0).contains(&lng)
 }
 
+fn is_valid_window(window: [f64; 2]) -> bool {
+    window.iter().all(|t| t.is_finite()) && window[0] <= window[1]
+}
+
+/// Raises the solver's cancel flag when dropped — e.g. when the client disconnects and
+/// axum drops the handler future — so the blocking task stops early.
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+fn error_response(status: StatusCode, message: impl Into<String>) -> axum::response::Response {
+    (status, Json(serde_json::json!({ "error": message.into() }))).into_response()
+}
+
 // ── Application State ──────────────────────────────────────────────────────────
 
 pub struct AppState {
@@ -46,9 +81,9 @@ pub struct Bencinera {
     pub lng:       f64,
     pub nombre:    String,
     pub direccion: String,
-    pub precio_93: Option<String>,
-    pub precio_95: Option<String>,
-    pub precio_97: Option<String>,
+    pub precio_93: Option<f64>,
+    pub precio_95: Option<f64>,
+    pub precio_97: Option<f64>,
 }
 
 // ── CNE Stations ────────────────────────────────────────────────────────────
@@ -59,9 +94,70 @@ pub struct CneStation {
     pub lng:       f64,
     pub nombre:    String,
     pub direccion: String,
-    pub precio_93: Option<String>,
-    pub precio_95: Option<String>,
-    pub precio_97: Option<String>,
+    pub precio_93: Option<f64>,
+    pub precio_95: Option<f64>,
+    pub precio_97: Option<f64>,
+}
+
+impl CneStation {
+    /// Price per litre of fuel `grade` (93, 95 or 97), when the station sells it.
+    pub fn price(&self, grade: u32) -> Option<f64> {
+        match grade {
+            93 => self.precio_93,
+            95 => self.precio_95,
+            97 => self.precio_97,
+            _ => None,
+        }
+    }
+}
+
+/// Parses a CNE price per litre, sent as a number or as text such as `"1249"`,
+/// `"1.249"`, `"1249,5"` or `"$1.249,50"`. Either `.` or `,` may separate thousands or
+/// decimals; a lone separator followed by three digits could be either, so the reading
+/// within `PRICE_RANGE_CLP` wins. Returns `None` when no reading is a plausible price.
+fn parse_price(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64().filter(|p| PRICE_RANGE_CLP.contains(p)),
+        serde_json::Value::String(text) => {
+            let text: String = text.trim().trim_start_matches('$').chars().filter(|c| !c.is_whitespace()).collect();
+            price_readings(&text).into_iter().find(|p| PRICE_RANGE_CLP.contains(p))
+        }
+        _ => None,
+    }
+}
+
+/// Every number `text` can be read as: all separators as thousands separators, or the
+/// last one as the decimal point after thousands separators of the other kind. Thousands
+/// separators must be followed by exactly three digits.
+fn price_readings(text: &str) -> Vec<f64> {
+    let groups: Vec<&str> = text.split(['.', ',']).collect();
+    if groups.iter().any(|g| g.is_empty() || !g.bytes().all(|b| b.is_ascii_digit())) {
+        return Vec::new();
+    }
+    let separators: Vec<char> = text.chars().filter(|&c| c == '.' || c == ',').collect();
+    let thousands = |groups: &[&str], separators: &[char]| {
+        groups[1..].iter().all(|g| g.len() == 3) && separators.windows(2).all(|w| w[0] == w[1])
+    };
+    let mut readings = Vec::new();
+    if thousands(&groups, &separators) {
+        readings.extend(groups.concat().parse::<f64>().ok());
+    }
+    if let Some((&decimal, rest)) = separators.split_last() {
+        let (fraction, whole) = groups.split_last().expect("one group more than separators");
+        if thousands(whole, rest) && !rest.contains(&decimal) {
+            readings.extend(format!("{}.{}", whole.concat(), fraction).parse::<f64>().ok());
+        }
+    }
+    readings
+}
+
+/// The stations selling fuel `grade`, and the same stations with that grade's price.
+fn stations_selling(cne: &[CneStation], grade: u32) -> (Vec<CneStation>, Vec<Station>) {
+    let selling: Vec<CneStation> = cne.iter().filter(|s| s.price(grade).is_some()).cloned().collect();
+    let stations = selling.iter()
+        .map(|s| Station { lat: s.lat, lng: s.lng, price: s.price(grade).expect("only stations with a price") })
+        .collect();
+    (selling, stations)
 }
 
 fn parse_cne_station(s: &serde_json::Value) -> Option<CneStation> {
@@ -77,9 +173,9 @@ fn parse_cne_station(s: &serde_json::Value) -> Option<CneStation> {
         lng,
         nombre,
         direccion,
-        precio_93: precios["93"]["precio"].as_str().map(|p| p.to_string()),
-        precio_95: precios["95"]["precio"].as_str().map(|p| p.to_string()),
-        precio_97: precios["97"]["precio"].as_str().map(|p| p.to_string()),
+        precio_93: parse_price(&precios["93"]["precio"]),
+        precio_95: parse_price(&precios["95"]["precio"]),
+        precio_97: parse_price(&precios["97"]["precio"]),
     })
 }
 
@@ -97,11 +193,28 @@ pub async fn login_cne(client: &reqwest::Client, email: &str, password: &str) ->
 
 // ── OSRM Road-Time Matrix ─────────────────────────────────────────────────────
 
+/// Road times and distances between every pair of coordinates.
+struct OsrmTable {
+    /// Seconds
+    durations: Vec<Vec<f32>>,
+    /// Metres; `None` when the server leaves them out
+    distances: Option<Vec<Vec<f32>>>,
+}
+
 /// Parses the `durations` array from an OSRM table response into an NxN matrix (seconds).
 /// Returns `None` if the key is missing or the structure is malformed.
 /// Unreachable pairs (JSON `null`) become `f32::MAX`.
 fn parse_osrm_durations(body: &serde_json::Value) -> Option<Vec<Vec<f32>>> {
-    body["durations"].as_array()?.iter().map(|row| {
+    parse_osrm_table(body, "durations")
+}
+
+/// Same as `parse_osrm_durations`, for the `distances` array (metres).
+fn parse_osrm_distances(body: &serde_json::Value) -> Option<Vec<Vec<f32>>> {
+    parse_osrm_table(body, "distances")
+}
+
+fn parse_osrm_table(body: &serde_json::Value, key: &str) -> Option<Vec<Vec<f32>>> {
+    body[key].as_array()?.iter().map(|row| {
         row.as_array().map(|r| {
             // null entries (unreachable pairs) become f32::MAX — not f64::MAX as f32 which overflows to inf
             r.iter().map(|v| v.as_f64().map_or(f32::MAX, |x| x as f32)).collect()
@@ -109,22 +222,26 @@ fn parse_osrm_durations(body: &serde_json::Value) -> Option<Vec<Vec<f32>>> {
     }).collect()
 }
 
-/// Fetches an NxN travel-time matrix (seconds) from the OSRM public table API.
-/// Returns `None` on timeout, HTTP error, or malformed response — caller falls back to haversine.
-async fn fetch_osrm_matrix(client: &reqwest::Client, coords: &[(f64, f64)]) -> Option<Vec<Vec<f32>>> {
+/// Fetches NxN travel-time (seconds) and distance (metres) matrices from the OSRM public
+/// table API. Returns `None` on timeout, HTTP error, or malformed durations — caller falls
+/// back to haversine.
+async fn fetch_osrm_table(client: &reqwest::Client, coords: &[(f64, f64)]) -> Option<OsrmTable> {
     // OSRM expects lng,lat order
     let coord_str = coords.iter()
         .map(|(lat, lng)| format!("{},{}", lng, lat))
         .collect::<Vec<_>>()
         .join(";");
-    let url = format!("{}/{}?annotations=duration", OSRM_BASE, coord_str);
+    let url = format!("{}/{}?annotations=duration,distance", OSRM_BASE, coord_str);
     let resp = tokio::time::timeout(
         Duration::from_secs(OSRM_TIMEOUT_SECS),
         client.get(&url).send(),
     ).await.ok()?.ok()?;
     if !resp.status().is_success() { return None; }
     let body: serde_json::Value = resp.json().await.ok()?;
-    parse_osrm_durations(&body)
+    // Tour::with_matrix needs one full row per coordinate
+    let square = |m: &Vec<Vec<f32>>| m.len() == coords.len() && m.iter().all(|row| row.len() == coords.len());
+    let durations = parse_osrm_durations(&body).filter(square)?;
+    Some(OsrmTable { durations, distances: parse_osrm_distances(&body).filter(square) })
 }
 
 pub async fn fetch_cne_stations(client: &reqwest::Client, token: &str) -> Vec<CneStation> {
@@ -244,24 +361,199 @@ pub async fn bencineras(
             lng:       s.lng,
             nombre:    s.nombre.clone(),
             direccion: s.direccion.clone(),
-            precio_93: s.precio_93.clone(),
-            precio_95: s.precio_95.clone(),
-            precio_97: s.precio_97.clone(),
+            precio_93: s.precio_93,
+            precio_95: s.precio_95,
+            precio_97: s.precio_97,
         })
         .collect();
 
     (StatusCode::OK, Json(stations)).into_response()
 }
 
+/// Stations selling a fuel grade within a detour of a route, cheapest or closest first.
+pub async fn bencineras_en_ruta(
+    State(state): State<SharedState>,
+    Json(payload): Json<RouteStationsRequest>,
+) -> impl IntoResponse {
+    let points: Vec<(f64, f64)> = match (&payload.geometry, &payload.coordinates) {
+        (Some(line), _) => line.coordinates.iter().map(|&[lng, lat]| (lat, lng)).collect(),
+        (None, Some(coordinates)) => coordinates.iter().map(|c| (c.lat, c.lng)).collect(),
+        (None, None) => return error_response(StatusCode::BAD_REQUEST, "Send the route as coordinates or geometry."),
+    };
+    if points.len() < 2 || points.len() > MAX_GEOMETRY_POINTS {
+        return error_response(StatusCode::BAD_REQUEST,
+            format!("The route needs between 2 and {} points.", MAX_GEOMETRY_POINTS));
+    }
+    if points.iter().any(|&(lat, lng)| !is_valid_coord(lat, lng)) {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid coordinates.");
+    }
+    let grade = payload.fuel_grade.unwrap_or(93);
+    if ![93, 95, 97].contains(&grade) {
+        return error_response(StatusCode::BAD_REQUEST, "fuel_grade must be 93, 95 or 97.");
+    }
+    let max_detour_km = payload.max_detour_km.unwrap_or(DEFAULT_DETOUR_KM);
+    if !(0.0..=MAX_DETOUR_KM).contains(&max_detour_km) {
+        return error_response(StatusCode::BAD_REQUEST,
+            format!("max_detour_km must be between 0 and {}.", MAX_DETOUR_KM));
+    }
+    let by_detour = match payload.sort.as_deref() {
+        None | Some("price") => false,
+        Some("detour") => true,
+        Some(_) => return error_response(StatusCode::BAD_REQUEST, "sort must be \"price\" or \"detour\"."),
+    };
+
+    let (selling, stations) = stations_selling(&state.cne_stations.read().await, grade);
+    let found = match tokio::task::spawn_blocking(move || fuel::along_route(&points, &stations, max_detour_km)).await {
+        Ok(found) => found,
+        Err(_) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Station search failed."),
+    };
+    let mut ranked: Vec<RouteStation> = found.into_iter()
+        .map(|(i, detour_km)| {
+            let s = &selling[i];
+            RouteStation {
+                lat:       s.lat,
+                lng:       s.lng,
+                nombre:    s.nombre.clone(),
+                direccion: s.direccion.clone(),
+                price:     s.price(grade).expect("only stations with a price"),
+                detour_km,
+                detour_secs: detour_km / ASSUMED_SPEED_KMH * 3600.0,
+            }
+        })
+        .collect();
+    ranked.sort_by(|a, b| if by_detour {
+        a.detour_km.total_cmp(&b.detour_km).then(a.price.total_cmp(&b.price))
+    } else {
+        a.price.total_cmp(&b.price).then(a.detour_km.total_cmp(&b.detour_km))
+    });
+    ranked.truncate(payload.limit.unwrap_or(DEFAULT_ROUTE_STATIONS));
+
+    (StatusCode::OK, Json(ranked)).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct RouteStationsRequest {
+    /// Stops in driving order, e.g. the `route` returned by `/solve`
+    pub coordinates: Option<Vec<LatLng>>,
+    /// Road geometry from `/route-geometry` (`routes[0].geometry`); used instead of
+    /// `coordinates` when both are sent, as it follows the streets
+    pub geometry: Option<LineString>,
+    /// 93 (default), 95 or 97
+    pub fuel_grade: Option<u32>,
+    /// Most distance a station may add to the route, in km (default 2)
+    pub max_detour_km: Option<f64>,
+    /// "price" (default) ranks the cheapest first, "detour" the closest
+    pub sort: Option<String>,
+    /// Stations returned (default 20)
+    pub limit: Option<usize>,
+}
+
+/// GeoJSON LineString, as returned by OSRM with `geometries=geojson`.
+#[derive(Deserialize)]
+pub struct LineString {
+    /// `[lng, lat]` pairs, in GeoJSON order
+    pub coordinates: Vec<[f64; 2]>,
+}
+
+#[derive(Serialize)]
+pub struct RouteStation {
+    pub lat:       f64,
+    pub lng:       f64,
+    pub nombre:    String,
+    pub direccion: String,
+    /// CLP per litre of `fuel_grade`
+    pub price:     f64,
+    /// Distance stopping at the station adds to the route
+    pub detour_km: f64,
+    /// `detour_km` driven at an assumed average speed
+    pub detour_secs: f64,
+}
+
 #[derive(Deserialize)]
 pub struct LatLng {
     pub lat: f64,
     pub lng: f64,
+    /// Opening hours `[earliest, latest]` in seconds on the `departure_secs` clock
+    pub time_window: Option<[f64; 2]>,
+    /// Time spent at the stop, in seconds
+    pub service_secs: Option<f64>,
+    /// Load picked up at the stop when routing several vehicles (default 1)
+    pub demand: Option<f64>,
+    /// Pins the stop to this place in the visiting order; 1 is the first stop after the start
+    pub position: Option<usize>,
+    /// Value of visiting the stop when a distance or duration budget means some must be
+    /// skipped (default 1)
+    pub reward: Option<f64>,
+    /// Stops sharing a cluster id are alternatives, e.g. stations from `/bencineras`;
+    /// exactly one of them is visited
+    pub cluster: Option<usize>,
 }
 
 #[derive(Deserialize)]
 pub struct SolveRequest {
     pub coordinates: Vec<LatLng>,
+    /// Seed for the solver's RNG; identical requests with the same seed return the same route.
+    pub seed: Option<u64>,
+    /// Index into `coordinates` of the first stop (defaults to 0 when returning to start).
+    pub start_index: Option<usize>,
+    /// Index into `coordinates` of the last stop; requires `return_to_start: false`.
+    pub end_index: Option<usize>,
+    /// Close the loop back to the first stop (default `true`).
+    pub return_to_start: Option<bool>,
+    /// Time the route leaves its first stop, in seconds (e.g. since midnight)
+    pub departure_secs: Option<f64>,
+    /// Split the stops across this many vehicles, all starting and ending at `start_index`;
+    /// the solver options (`strategy`, `construction`, `starts`, ...) do not apply
+    pub vehicles: Option<usize>,
+    /// Load each vehicle can carry, in the same units as `demand` (default unlimited)
+    pub capacity: Option<f64>,
+    /// Solver time budget; the best route found so far is returned when it runs out
+    pub time_limit_ms: Option<u64>,
+    /// Solver preset: "fast", "balanced" (default), "best", "annealing", "ils", "genetic"
+    /// or "ants"
+    pub strategy: Option<String>,
+    /// Overrides the strategy's first route: one of `Construction::NAMES`, e.g.
+    /// "christofides" or "farthest_insertion"
+    pub construction: Option<String>,
+    /// Annealing moves, ILS kicks, genetic generations or colony iterations for the
+    /// metaheuristic strategies
+    pub iterations: Option<usize>,
+    /// Starting annealing temperature, as a fraction of the mean edge cost
+    pub temperature: Option<f64>,
+    /// Solve from this many starting routes and keep the best (default 1)
+    pub starts: Option<usize>,
+    /// Worker threads for `starts`; 1 by default, capped at the server's core count
+    pub threads: Option<usize>,
+    /// `[pickup, delivery]` pairs of indices into `coordinates`: each pickup is visited
+    /// before its delivery
+    pub pickup_delivery: Option<Vec<[usize; 2]>>,
+    /// General `[before, after]` ordering constraints between indices into `coordinates`
+    pub precedence: Option<Vec<[usize; 2]>>,
+    /// Groups of indices into `coordinates` visited in the order listed, though not
+    /// necessarily back to back
+    pub ordered_groups: Option<Vec<Vec<usize>>>,
+    /// Longest route allowed, in straight-line km, so the stops are ordered without OSRM
+    /// and `routing` is "haversine"; the stops worth the most `reward` that fit are
+    /// visited and the rest returned in `skipped`. Rejected with 422 when the leg between
+    /// fixed start and end alone is longer
+    pub max_distance_km: Option<f64>,
+    /// Same as `max_distance_km`, as OSRM driving time in seconds
+    pub max_duration_secs: Option<f64>,
+    /// Distance a full tank lasts, in km; refuelling stops from the CNE stations are added
+    /// wherever the route would otherwise run dry. Fuel is planned on straight-line
+    /// distances, while the stops keep OSRM road times when available
+    pub tank_range_km: Option<f64>,
+    /// Fuel in the tank at the start, as a fraction of a full tank (default 1)
+    pub fuel_level: Option<f64>,
+    /// Fuel grade to refuel with and price trips at: 93 (default), 95 or 97
+    pub fuel_grade: Option<u32>,
+    /// Vehicle fuel economy in km per litre; adds a `trip_cost` estimate in CLP
+    pub km_per_litre: Option<f64>,
+    /// Driver wage in CLP per hour, added to `trip_cost`
+    pub driver_cost_per_hour: Option<f64>,
+    /// Fuel price for `trip_cost`: "nearest" (default), from the CNE station nearest the
+    /// first stop, or "average" over every station
+    pub fuel_price: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -271,14 +563,108 @@ pub struct SolveResponse {
     pub total_distance_km: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub total_travel_time_secs: Option<f32>,
-    /// "osrm" when road times were used, "haversine" on fallback
+    /// "osrm" when the stops were ordered on road times, "haversine" with `max_distance_km`
+    /// or when OSRM is unavailable
     pub routing: &'static str,
+    /// Seed used by the solver (the requested one, or a random one) to reproduce this route
+    pub seed: u64,
+    /// True when the time budget ran out and `route` is the best found so far
+    pub timed_out: bool,
+    /// True when the exact solver proved `route` is the shortest possible
+    pub optimal: bool,
+    /// No route can cost less than this, in the same units as the total (single vehicle,
+    /// up to 500 stops)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lower_bound: Option<f32>,
+    /// How far the total is above `lower_bound`, in percent; an upper limit on the true gap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gap_percent: Option<f32>,
+    /// Per-stop timing, present when the request had time windows or service times
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Vec<StopSchedule>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_windows_met: Option<bool>,
+    /// One route per vehicle when `vehicles` was requested; `route` then chains them all
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vehicle_routes: Option<Vec<VehicleRouteResponse>>,
+    /// Indices of the stops left out to stay within `max_distance_km` or `max_duration_secs`,
+    /// or because another stop of their `cluster` was visited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<Vec<usize>>,
+    /// Summed `reward` of the stops on the route, with a distance or duration budget
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collected_reward: Option<f32>,
+    /// Estimated cost of driving the route, when `km_per_litre` was given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trip_cost: Option<TripCostResponse>,
+}
+
+#[derive(Serialize)]
+pub struct TripCostResponse {
+    /// Road distance with OSRM, straight-line distance otherwise
+    pub distance_km: f64,
+    /// OSRM driving time, plus waits and service with time windows; otherwise the
+    /// distance at an assumed average speed
+    pub hours: f64,
+    pub fuel_litres: f64,
+    /// CLP per litre of `fuel_grade`
+    pub fuel_price: f64,
+    /// Station the price comes from, with `fuel_price: "nearest"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fuel_station: Option<String>,
+    pub fuel_clp: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub driver_clp: Option<f64>,
+    pub total_clp: f64,
+}
+
+#[derive(Serialize)]
+pub struct VehicleRouteResponse {
+    pub route: Vec<RoutePoint>,
+    /// Distance (km) or travel time (s), depending on `routing`
+    pub cost: f32,
+    pub load: f32,
+}
+
+#[derive(Serialize)]
+pub struct StopSchedule {
+    /// Index of the stop in the request's `coordinates`
+    pub index: usize,
+    pub arrival_secs: f32,
+    pub wait_secs: f32,
+    pub departure_secs: f32,
+    pub late_secs: f32,
+    pub window_met: bool,
 }
 
 #[derive(Serialize)]
 pub struct RoutePoint {
     pub lat: f64,
     pub lng: f64,
+    /// Set on refuelling stops added for `tank_range_km`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fuel_stop: Option<FuelStopResponse>,
+}
+
+#[derive(Serialize)]
+pub struct FuelStopResponse {
+    pub nombre: String,
+    pub direccion: String,
+    /// Price per litre of `fuel_grade`, in CLP
+    pub price: f64,
+    /// Distance the stop adds to the route
+    pub detour_km: f64,
+}
+
+/// What the blocking solver task hands back to the handler.
+struct SolveOutcome {
+    tour: Tour,
+    vehicle_plan: Option<Result<Vec<VehicleRoute>, VrpError>>,
+    skipped: Option<Vec<usize>>,
+    selection: Option<Result<Selection, OrienteeringError>>,
+    timed_out: bool,
+    optimal: bool,
+    lower_bound: Option<f32>,
 }
 
 pub async fn solve(
@@ -286,28 +672,200 @@ pub async fn solve(
     Json(payload): Json<SolveRequest>,
 ) -> impl IntoResponse {
     if payload.coordinates.len() < 3 {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({ "error": "At least 3 coordinates are required." })),
-        )
-            .into_response();
+        return error_response(StatusCode::BAD_REQUEST, "At least 3 coordinates are required.");
     }
 
     if payload.coordinates.len() > MAX_NODES {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({ "error": format!("Maximum {} coordinates allowed.", MAX_NODES) })),
-        )
-            .into_response();
+        return error_response(StatusCode::BAD_REQUEST, format!("Maximum {} coordinates allowed.", MAX_NODES));
     }
 
     if payload.coordinates.iter().any(|c| !is_valid_coord(c.lat, c.lng)) {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({ "error": "Invalid coordinates." })),
-        )
-            .into_response();
+        return error_response(StatusCode::BAD_REQUEST, "Invalid coordinates.");
+    }
+
+    let mut strategy = match payload.strategy.as_deref() {
+        None => Strategy::default(),
+        Some(name) => match Strategy::named(name) {
+            Some(s) => s,
+            None => return error_response(StatusCode::BAD_REQUEST,
+                format!("Unknown strategy. Use one of: {}.", Strategy::NAMES.join(", "))),
+        },
+    };
+    if payload.iterations.is_some_and(|n| n > MAX_ITERATIONS) {
+        return error_response(StatusCode::BAD_REQUEST, format!("Maximum {} iterations allowed.", MAX_ITERATIONS));
+    }
+    if payload.temperature.is_some_and(|t| !t.is_finite() || t <= 0.0) {
+        return error_response(StatusCode::BAD_REQUEST, "Temperature must be a positive number.");
+    }
+    strategy.tune(payload.iterations, payload.temperature);
+    if let Some(name) = payload.construction.as_deref() {
+        match Construction::named(name) {
+            Some(c) => strategy.construction = c,
+            None => return error_response(StatusCode::BAD_REQUEST,
+                format!("Unknown construction. Use one of: {}.", Construction::NAMES.join(", "))),
+        }
+    }
+
+    let starts = payload.starts.unwrap_or(1);
+    if starts == 0 || starts > MAX_STARTS {
+        return error_response(StatusCode::BAD_REQUEST, format!("starts must be between 1 and {}.", MAX_STARTS));
     }
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let threads = payload.threads.unwrap_or(DEFAULT_THREADS).clamp(1, cores);
+
+    let timed = payload.departure_secs.is_some()
+        || payload.coordinates.iter().any(|c| c.time_window.is_some() || c.service_secs.is_some());
+    if timed {
+        if payload.coordinates.iter().any(|c| c.time_window.is_some_and(|w| !is_valid_window(w))
+            || c.service_secs.is_some_and(|s| !s.is_finite() || s < 0.0))
+            || payload.departure_secs.is_some_and(|d| !d.is_finite())
+        {
+            return error_response(StatusCode::BAD_REQUEST, "Invalid time window, service or departure time.");
+        }
+        if payload.coordinates.len() > OSRM_MAX_COORDS {
+            return error_response(StatusCode::BAD_REQUEST,
+                format!("Time windows are limited to {} coordinates.", OSRM_MAX_COORDS));
+        }
+    }
+
+    if let Some(vehicles) = payload.vehicles {
+        if vehicles == 0 || vehicles > MAX_VEHICLES {
+            return error_response(StatusCode::BAD_REQUEST,
+                format!("vehicles must be between 1 and {}.", MAX_VEHICLES));
+        }
+        if payload.capacity.is_some_and(|c| !c.is_finite() || c <= 0.0)
+            || payload.coordinates.iter().any(|c| c.demand.is_some_and(|d| !d.is_finite() || d < 0.0))
+        {
+            return error_response(StatusCode::BAD_REQUEST, "Invalid capacity or demand.");
+        }
+        if timed || !payload.return_to_start.unwrap_or(true) {
+            return error_response(StatusCode::BAD_REQUEST,
+                "vehicles cannot be combined with time windows or open paths.");
+        }
+        // Vehicle routes are built with savings and polished with 2-opt and Or-opt, not a strategy
+        if starts > 1 || payload.strategy.is_some() || payload.construction.is_some()
+            || payload.iterations.is_some() || payload.temperature.is_some()
+        {
+            return error_response(StatusCode::BAD_REQUEST,
+                "vehicles cannot be combined with starts, strategy, construction, iterations or temperature.");
+        }
+    }
+
+    let limit = payload.max_distance_km.or(payload.max_duration_secs);
+    if limit.is_some() {
+        if payload.max_distance_km.is_some() && payload.max_duration_secs.is_some() {
+            return error_response(StatusCode::BAD_REQUEST, "Use either max_distance_km or max_duration_secs, not both.");
+        }
+        if limit.is_some_and(|l| !l.is_finite() || l <= 0.0)
+            || payload.coordinates.iter().any(|c| c.reward.is_some_and(|r| !r.is_finite() || r < 0.0))
+        {
+            return error_response(StatusCode::BAD_REQUEST, "Invalid distance or duration budget, or reward.");
+        }
+        if timed || payload.vehicles.is_some() {
+            return error_response(StatusCode::BAD_REQUEST,
+                "max_distance_km and max_duration_secs cannot be combined with vehicles or time windows.");
+        }
+    }
+
+    let n = payload.coordinates.len();
+    if payload.start_index.is_some_and(|i| i >= n) || payload.end_index.is_some_and(|i| i >= n) {
+        return error_response(StatusCode::BAD_REQUEST, "start_index and end_index must refer to a coordinate.");
+    }
+
+    let (start, end) = if payload.return_to_start.unwrap_or(true) {
+        let start = payload.start_index.unwrap_or(0);
+        if payload.end_index.is_some_and(|e| e != start) {
+            return error_response(StatusCode::BAD_REQUEST, "end_index requires return_to_start to be false.");
+        }
+        (Some(start), Some(start))
+    } else {
+        (payload.start_index, payload.end_index)
+    };
+    if limit.is_some() && start.is_none() {
+        return error_response(StatusCode::BAD_REQUEST,
+            "max_distance_km and max_duration_secs need a fixed start: set start_index or return_to_start.");
+    }
+
+    let precedence: Vec<(usize, usize)> = payload.pickup_delivery.iter()
+        .chain(&payload.precedence)
+        .flatten()
+        .map(|&[before, after]| (before, after))
+        .chain(payload.ordered_groups.iter().flatten().flat_map(|group| group.windows(2).map(|w| (w[0], w[1]))))
+        .collect();
+    if precedence.iter().any(|&(a, b)| a >= n || b >= n) {
+        return error_response(StatusCode::BAD_REQUEST,
+            "pickup_delivery, precedence and ordered_groups must refer to coordinates.");
+    }
+    let pins: Vec<(usize, usize)> = payload.coordinates.iter().enumerate()
+        .filter_map(|(i, c)| c.position.map(|p| (i, p)))
+        .collect();
+    if (!precedence.is_empty() || !pins.is_empty()) && payload.vehicles.is_some() {
+        return error_response(StatusCode::BAD_REQUEST,
+            "vehicles cannot be combined with order constraints or pinned positions.");
+    }
+    let constraints = match OrderConstraints::new(n, start, end, &precedence, &pins) {
+        Ok(c) => c,
+        Err(e) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()),
+    };
+    if limit.is_some() && !constraints.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST,
+            "max_distance_km and max_duration_secs cannot be combined with order constraints.");
+    }
+
+    // Dense cluster ids, so clients can number clusters however they like
+    let mut cluster_ids: Vec<usize> = payload.coordinates.iter().filter_map(|c| c.cluster).collect();
+    cluster_ids.sort_unstable();
+    cluster_ids.dedup();
+    let cluster: Vec<Option<usize>> = payload.coordinates.iter()
+        .map(|c| c.cluster.map(|id| cluster_ids.binary_search(&id).expect("every cluster id was collected")))
+        .collect();
+    let clustered = !cluster_ids.is_empty();
+    if clustered {
+        if timed || payload.vehicles.is_some() || limit.is_some() || !constraints.is_empty() {
+            return error_response(StatusCode::BAD_REQUEST,
+                "cluster cannot be combined with vehicles, time windows, budgets or order constraints.");
+        }
+        if let (Some(s), Some(e)) = (start, end) {
+            if s != e && cluster[s].is_some() && cluster[s] == cluster[e] {
+                return error_response(StatusCode::BAD_REQUEST, "start_index and end_index cannot share a cluster.");
+            }
+        }
+    }
+
+    let grade = payload.fuel_grade.unwrap_or(93);
+    if ![93, 95, 97].contains(&grade) {
+        return error_response(StatusCode::BAD_REQUEST, "fuel_grade must be 93, 95 or 97.");
+    }
+    let tank = match payload.tank_range_km {
+        None => None,
+        Some(range_km) => {
+            let level = payload.fuel_level.unwrap_or(1.0);
+            if !range_km.is_finite() || range_km <= 0.0 || !(0.0..=1.0).contains(&level) {
+                return error_response(StatusCode::BAD_REQUEST, "Invalid tank_range_km or fuel_level.");
+            }
+            if timed || payload.vehicles.is_some() || limit.is_some() {
+                return error_response(StatusCode::BAD_REQUEST,
+                    "tank_range_km cannot be combined with vehicles, time windows or budgets.");
+            }
+            Some(Tank { range_km, fuel_km: range_km * level })
+        }
+    };
+    let vehicle = match payload.km_per_litre {
+        None => None,
+        Some(km_per_litre) => {
+            if !km_per_litre.is_finite() || km_per_litre <= 0.0
+                || payload.driver_cost_per_hour.is_some_and(|c| !c.is_finite() || c < 0.0)
+            {
+                return error_response(StatusCode::BAD_REQUEST, "Invalid km_per_litre or driver_cost_per_hour.");
+            }
+            Some(Vehicle { km_per_litre, driver_per_hour: payload.driver_cost_per_hour })
+        }
+    };
+    let average_price = match payload.fuel_price.as_deref() {
+        None | Some("nearest") => false,
+        Some("average") => true,
+        Some(_) => return error_response(StatusCode::BAD_REQUEST, "fuel_price must be \"nearest\" or \"average\"."),
+    };
 
     let positions: Vec<(f32, f32)> = payload
         .coordinates
@@ -316,53 +874,282 @@ pub async fn solve(
         .collect();
 
     let coords: Vec<(f64, f64)> = payload.coordinates.iter().map(|c| (c.lat, c.lng)).collect();
-    let osrm_matrix = if coords.len() <= OSRM_MAX_COORDS {
-        fetch_osrm_matrix(&state.client, &coords).await
+    // Distance budgets are in kilometres, so they stay on haversine distances
+    let osrm_table = if coords.len() <= OSRM_MAX_COORDS && payload.max_distance_km.is_none() {
+        fetch_osrm_table(&state.client, &coords).await
     } else {
         None
     };
+    let (osrm_matrix, road_distances) = match osrm_table {
+        Some(table) => (Some(table.durations), table.distances),
+        None => (None, None),
+    };
     let use_osrm = osrm_matrix.is_some();
+    if timed && !use_osrm {
+        return error_response(StatusCode::BAD_GATEWAY,
+            "Time windows need OSRM road times, which are unavailable.");
+    }
+    if payload.max_duration_secs.is_some() && !use_osrm {
+        return error_response(StatusCode::BAD_GATEWAY,
+            "max_duration_secs needs OSRM road times, which are unavailable.");
+    }
+    let windows: Vec<Option<TimeWindow>> = payload.coordinates.iter()
+        .map(|c| c.time_window.map(|[earliest, latest]| TimeWindow {
+            earliest: earliest as f32,
+            latest: latest as f32,
+        }))
+        .collect();
+    let service: Vec<f32> = payload.coordinates.iter()
+        .map(|c| c.service_secs.unwrap_or(0.0) as f32)
+        .collect();
+    let departure = payload.departure_secs.unwrap_or(0.0) as f32;
+    let seed = payload.seed.unwrap_or_else(rand::random);
+    let fleet = payload.vehicles.map(|vehicles| Fleet {
+        vehicles,
+        capacity: payload.capacity.map_or(f32::INFINITY, |c| c as f32),
+    });
+    let demand: Vec<f32> = payload.coordinates.iter()
+        .map(|c| c.demand.unwrap_or(1.0) as f32)
+        .collect();
+    let reward: Vec<f32> = payload.coordinates.iter()
+        .map(|c| c.reward.unwrap_or(1.0) as f32)
+        .collect();
+
+    let time_limit = Duration::from_millis(
+        payload.time_limit_ms.unwrap_or(SOLVER_TIMEOUT_SECS * 1000).min(SOLVER_TIMEOUT_SECS * 1000),
+    );
+    let cancel = Arc::new(AtomicBool::new(false));
+    let _cancel_on_drop = CancelOnDrop(Arc::clone(&cancel));
+    let budget = Budget::until(Instant::now() + time_limit).with_cancel(cancel);
 
+    let mut tour = match osrm_matrix {
+        Some(matrix) => match Tour::with_matrix(positions, matrix) {
+            Ok(tour) => tour,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+        },
+        None => Tour::new(positions),
+    };
     let result = tokio::time::timeout(
-        Duration::from_secs(SOLVER_TIMEOUT_SECS),
+        time_limit + Duration::from_secs(SOLVER_GRACE_SECS),
         tokio::task::spawn_blocking(move || {
-            let mut tour = if let Some(matrix) = osrm_matrix {
-                Tour::with_matrix(positions, matrix)
+            tour.set_seed(seed);
+            tour.set_endpoints(start, end);
+            tour.set_order_constraints(constraints);
+            tour.set_budget(budget);
+            if let Some(fleet) = fleet {
+                let depot = start.unwrap_or(0);
+                let routes = vrp::solve(&mut tour, depot, &demand, &fleet);
+                let timed_out = tour.budget().exhausted();
+                return SolveOutcome {
+                    tour,
+                    vehicle_plan: Some(routes),
+                    skipped: None,
+                    selection: None,
+                    timed_out,
+                    optimal: false,
+                    lower_bound: None,
+                };
+            }
+            if starts > 1 {
+                tour.solve_multi_start(&strategy, starts, threads);
             } else {
-                Tour::new(positions)
-            };
-            tour.nearest_neighbour_tour();
-            tour.two_opt();
-            tour.or_opt();
+                tour.solve(&strategy);
+            }
+            if clustered {
+                let skipped = gtsp::choose(&mut tour, &cluster);
+                let timed_out = tour.budget().exhausted();
+                return SolveOutcome {
+                    tour,
+                    vehicle_plan: None,
+                    skipped: Some(skipped),
+                    selection: None,
+                    timed_out,
+                    optimal: false,
+                    lower_bound: None,
+                };
+            }
+            if let Some(limit) = limit {
+                let selection = orienteering::select(&mut tour, &reward, limit as f32);
+                let timed_out = tour.budget().exhausted();
+                return SolveOutcome {
+                    tour,
+                    vehicle_plan: None,
+                    skipped: None,
+                    selection: Some(selection),
+                    timed_out,
+                    optimal: false,
+                    lower_bound: None,
+                };
+            }
+            let mut optimal = false;
+            if timed {
+                tour.set_time_windows(windows, service, departure);
+                tour.time_window_search();
+            } else if tour.nodes.len() <= EXACT_MAX_NODES {
+                optimal = tour.solve_exact();
+            }
             tour.calculate_cost();
-            tour
+            let timed_out = tour.budget().exhausted();
+            let lower_bound = if optimal { Some(tour.cost) } else { tour.lower_bound() };
+            SolveOutcome {
+                tour,
+                vehicle_plan: None,
+                skipped: None,
+                selection: None,
+                timed_out,
+                optimal,
+                lower_bound,
+            }
         }),
     )
     .await;
 
-    let tour = match result {
+    let SolveOutcome { mut tour, vehicle_plan, skipped, selection, timed_out, optimal, lower_bound } = match result {
         Ok(Ok(t)) => t,
         Ok(Err(_)) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({ "error": "Solver failed." })),
-            )
-                .into_response();
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Solver failed.");
         }
         Err(_) => {
-            return (
-                StatusCode::REQUEST_TIMEOUT,
-                Json(serde_json::json!({ "error": "Solver timed out." })),
-            )
-                .into_response();
+            return error_response(StatusCode::REQUEST_TIMEOUT, "Solver timed out.");
         }
     };
 
-    let route: Vec<RoutePoint> = tour
-        .route
-        .iter()
-        .map(|n| RoutePoint { lat: n.x as f64, lng: n.y as f64 })
-        .collect();
+    let vehicle_routes = match vehicle_plan {
+        Some(Ok(routes)) => {
+            // Chain every vehicle's route so `route` and the total cover the whole plan
+            tour.route = routes.iter()
+                .flat_map(|r| r.stops.iter().map(|&id| tour.nodes[id].clone()))
+                .collect();
+            tour.cost = routes.iter().map(|r| r.cost).sum();
+            Some(routes.iter()
+                .map(|r| VehicleRouteResponse {
+                    route: r.stops.iter()
+                        .map(|&id| RoutePoint { lat: tour.nodes[id].x as f64, lng: tour.nodes[id].y as f64, fuel_stop: None })
+                        .collect(),
+                    cost: r.cost,
+                    load: r.load,
+                })
+                .collect())
+        }
+        Some(Err(e)) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()),
+        None => None,
+    };
+    let (skipped, collected_reward) = match selection {
+        Some(Ok(selection)) => (Some(selection.skipped), Some(selection.reward)),
+        Some(Err(e)) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()),
+        None => (skipped, None),
+    };
+
+    // Refuelling stops are planned on the final route, among the stations selling the grade,
+    // on haversine distances even when OSRM ordered the stops
+    let mut fuel_stops: Vec<(FuelStop, CneStation)> = Vec::new();
+    let mut detour_km = 0.0;
+    if let Some(tank) = tank {
+        let (selling, stations) = stations_selling(&state.cne_stations.read().await, grade);
+        let points: Vec<(f64, f64)> = tour.route.iter()
+            .map(|n| (payload.coordinates[n.id].lat, payload.coordinates[n.id].lng))
+            .collect();
+        match tokio::task::spawn_blocking(move || fuel::plan(&points, &stations, &tank)).await {
+            Ok(Ok(stops)) => {
+                fuel_stops = stops.into_iter()
+                    .map(|stop| {
+                        let station = selling[stop.station].clone();
+                        (stop, station)
+                    })
+                    .collect();
+            }
+            Ok(Err(FuelError::Stranded(at))) => {
+                return error_response(StatusCode::UNPROCESSABLE_ENTITY, format!(
+                    "The tank runs dry after stop {}: no station selling {} is in range.", tour.route[at].id, grade));
+            }
+            Err(_) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Solver failed."),
+        }
+        detour_km = fuel_stops.iter().map(|(stop, _)| stop.detour_km).sum::<f64>();
+        tour.cost += if use_osrm { detour_km / ASSUMED_SPEED_KMH * 3600.0 } else { detour_km } as f32;
+    }
+    let optimal = optimal && fuel_stops.is_empty();
+
+    let mut route: Vec<RoutePoint> = Vec::with_capacity(tour.route.len() + fuel_stops.len());
+    let mut pending = fuel_stops.into_iter().peekable();
+    for (k, n) in tour.route.iter().enumerate() {
+        route.push(RoutePoint { lat: n.x as f64, lng: n.y as f64, fuel_stop: None });
+        while let Some((stop, station)) = pending.next_if(|(stop, _)| stop.after == k) {
+            route.push(RoutePoint {
+                lat: station.lat,
+                lng: station.lng,
+                fuel_stop: Some(FuelStopResponse {
+                    price: station.price(grade).expect("only stations with a price"),
+                    nombre: station.nombre,
+                    direccion: station.direccion,
+                    detour_km: stop.detour_km,
+                }),
+            });
+        }
+    }
+
+    let (schedule, time_windows_met) = if timed {
+        let schedule: Vec<StopSchedule> = tour.schedule().iter()
+            .map(|t| StopSchedule {
+                index: t.id,
+                arrival_secs: t.arrival,
+                wait_secs: t.wait,
+                departure_secs: t.departure,
+                late_secs: t.late,
+                window_met: t.late <= 0.0,
+            })
+            .collect();
+        let met = schedule.iter().all(|s| s.window_met);
+        (Some(schedule), Some(met))
+    } else {
+        (None, None)
+    };
+
+    let trip_cost = match vehicle {
+        None => None,
+        Some(vehicle) => {
+            let (selling, stations) = stations_selling(&state.cne_stations.read().await, grade);
+            let (price, fuel_station) = if average_price {
+                (fuel::average_price(&stations), None)
+            } else {
+                let first = &payload.coordinates[tour.route[0].id];
+                let nearest = fuel::nearest(&stations, (first.lat, first.lng));
+                (nearest.map(|s| stations[s].price), nearest.map(|s| selling[s].nombre.clone()))
+            };
+            let Some(price) = price else {
+                return error_response(StatusCode::BAD_GATEWAY,
+                    format!("No CNE prices are available for grade {}.", grade));
+            };
+            let distance_km = match &road_distances {
+                Some(metres) => {
+                    tour.route.windows(2).map(|w| metres[w[0].id][w[1].id] as f64).sum::<f64>() / 1000.0 + detour_km
+                }
+                None if use_osrm => tour.route.windows(2)
+                    .map(|w| haversine_km(w[0].x as f64, w[0].y as f64, w[1].x as f64, w[1].y as f64))
+                    .sum::<f64>() + detour_km,
+                None => tour.cost as f64,
+            };
+            let hours = match schedule.as_ref().and_then(|s| s.last()) {
+                Some(last) => (last.arrival_secs - departure) as f64 / 3600.0,
+                None if use_osrm => tour.cost as f64 / 3600.0,
+                None => distance_km / ASSUMED_SPEED_KMH,
+            };
+            let cost = vehicle.trip_cost(distance_km, hours, price);
+            Some(TripCostResponse {
+                distance_km,
+                hours,
+                fuel_litres: cost.litres,
+                fuel_price: price,
+                fuel_station,
+                fuel_clp: cost.fuel_clp,
+                driver_clp: cost.driver_clp,
+                total_clp: cost.total_clp,
+            })
+        }
+    };
+
+    let gap_percent = lower_bound
+        .filter(|&lb| lb > 0.0)
+        .map(|lb| (tour.cost - lb).max(0.0) / lb * 100.0);
 
     let (total_distance_km, total_travel_time_secs, routing) = if use_osrm {
         (None, Some(tour.cost), "osrm")
@@ -372,7 +1159,23 @@ pub async fn solve(
 
     (
         StatusCode::OK,
-        Json(SolveResponse { route, total_distance_km, total_travel_time_secs, routing }),
+        Json(SolveResponse {
+            route,
+            total_distance_km,
+            total_travel_time_secs,
+            routing,
+            seed,
+            timed_out,
+            optimal,
+            lower_bound,
+            gap_percent,
+            schedule,
+            time_windows_met,
+            vehicle_routes,
+            skipped,
+            collected_reward,
+            trip_cost,
+        }),
     )
         .into_response()
 }
@@ -436,6 +1239,19 @@ mod tests {
         assert!(matrix.iter().all(|row| row.len() == 3));
     }
 
+    #[test]
+    fn is_valid_window_accepts_ordered_finite_bounds() {
+        assert!(is_valid_window([28_800.0, 36_000.0]));
+        assert!(is_valid_window([0.0, 0.0]));
+    }
+
+    #[test]
+    fn is_valid_window_rejects_reversed_or_non_finite_bounds() {
+        assert!(!is_valid_window([36_000.0, 28_800.0]));
+        assert!(!is_valid_window([f64::NAN, 10.0]));
+        assert!(!is_valid_window([0.0, f64::INFINITY]));
+    }
+
     #[test]
     fn is_valid_coord_valid_cases() {
         assert!(is_valid_coord(0.0, 0.0));
@@ -476,4 +1292,54 @@ mod tests {
         assert!(!is_valid_coord(-90.1, 0.0));
         assert!(!is_valid_coord(90.1, 0.0));
     }
+
+    // ── CNE Price Tests ───────────────────────────────────────────────────────
+
+    #[test]
+    fn parse_price_should_read_numbers_and_plain_text() {
+        assert_eq!(parse_price(&serde_json::json!(1249)), Some(1249.0));
+        assert_eq!(parse_price(&serde_json::json!(1302.5)), Some(1302.5));
+        assert_eq!(parse_price(&serde_json::json!(" 1249 ")), Some(1249.0));
+        assert_eq!(parse_price(&serde_json::json!("1302.5")), Some(1302.5));
+    }
+
+    #[test]
+    fn parse_price_should_handle_chilean_separators() {
+        assert_eq!(parse_price(&serde_json::json!("1.249")), Some(1249.0));
+        assert_eq!(parse_price(&serde_json::json!("1249,5")), Some(1249.5));
+        assert_eq!(parse_price(&serde_json::json!("$1.249,50")), Some(1249.5));
+        assert_eq!(parse_price(&serde_json::json!("1,249.50")), Some(1249.5));
+    }
+
+    #[test]
+    fn parse_price_should_read_ambiguous_separators_as_a_plausible_price() {
+        assert_eq!(parse_price(&serde_json::json!("1249.000")), Some(1249.0));
+        assert_eq!(parse_price(&serde_json::json!("999.500")), Some(999.5));
+        assert_eq!(parse_price(&serde_json::json!("1,249")), Some(1249.0));
+        assert_eq!(parse_price(&serde_json::json!("999,500")), Some(999.5));
+        assert_eq!(parse_price(&serde_json::json!("1.249,000")), Some(1249.0));
+    }
+
+    #[test]
+    fn parse_price_should_reject_missing_or_invalid_prices() {
+        for value in [serde_json::json!(null), serde_json::json!("n/a"), serde_json::json!(""),
+                      serde_json::json!("0"), serde_json::json!(-5), serde_json::json!({}),
+                      serde_json::json!("12"), serde_json::json!("1.249.000"), serde_json::json!(99_999),
+                      serde_json::json!("1.249.5"), serde_json::json!("1..249"), serde_json::json!("1e3")] {
+            assert_eq!(parse_price(&value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn parse_cne_station_should_keep_prices_by_grade() {
+        let body = serde_json::json!({
+            "razon_social": "Copec",
+            "ubicacion": { "latitud": "-33.45", "longitud": "-70.66", "direccion": " Av. Providencia 1234 " },
+            "precios": { "93": { "precio": "1.249" }, "95": { "precio": "1302,5" } }
+        });
+        let station = parse_cne_station(&body).unwrap();
+        assert_eq!((station.price(93), station.price(95), station.price(97)), (Some(1249.0), Some(1302.5), None));
+        assert_eq!(station.price(91), None);
+        assert_eq!(station.direccion, "Av. Providencia 1234");
+    }
 }
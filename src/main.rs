@@ -1,5 +1,11 @@
+pub mod fuel;
 pub mod handlers;
+pub mod genetic;
+pub mod gtsp;
+pub mod metric;
+pub mod orienteering;
 pub mod tour;
+pub mod vrp;
 
 use axum::response::Response;
 use axum::http::{header, StatusCode};
@@ -85,6 +91,7 @@ async fn main() {
         }))
         .route("/solve", axum::routing::post(handlers::solve))
         .route("/bencineras", axum::routing::get(handlers::bencineras))
+        .route("/bencineras/ruta", axum::routing::post(handlers::bencineras_en_ruta))
         .route("/route-geometry", axum::routing::post(handlers::route_geometry))
         .route("/status", axum::routing::get(handlers::status))
         .route("/debug-cne", axum::routing::get(handlers::debug_cne))
@@ -0,0 +1,110 @@
+use crate::tour::haversine_km;
+use std::fmt;
+
+/// Travel cost between two nodes, by id. `Tour` reads every distance through this trait,
+/// so a metric can be a stored matrix or computed on demand for very large instances.
+pub trait Metric: fmt::Debug + Send + Sync {
+    fn cost(&self, from: usize, to: usize) -> f32;
+
+    /// Whether each lookup is computed on demand, so caching the metric in a
+    /// `DistanceMatrix` speeds up the solvers (see `Tour::distance_matrix`).
+    fn lazy(&self) -> bool {
+        false
+    }
+}
+
+/// Dense n×n matrix stored row-major in one allocation: 4 bytes per entry and no branch
+/// on lookup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceMatrix {
+    n: usize,
+    data: Vec<f32>,
+}
+
+impl DistanceMatrix {
+    /// Builds the matrix by evaluating `cost(from, to)` for every pair.
+    pub fn from_fn(n: usize, cost: impl Fn(usize, usize) -> f32) -> Self {
+        let data = (0..n * n).map(|i| cost(i / n, i % n)).collect();
+        Self { n, data }
+    }
+
+    /// Flattens nested rows, or returns `None` unless there are as many rows as columns.
+    pub fn from_rows(rows: Vec<Vec<f32>>) -> Option<Self> {
+        let n = rows.len();
+        if rows.iter().any(|row| row.len() != n) {
+            return None;
+        }
+        Some(Self { n, data: rows.into_iter().flatten().collect() })
+    }
+
+    /// Number of nodes (rows).
+    pub fn len(&self) -> usize {
+        self.n
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+}
+
+impl Metric for DistanceMatrix {
+    fn cost(&self, from: usize, to: usize) -> f32 {
+        self.data[from * self.n + to]
+    }
+}
+
+/// Great-circle kilometres computed from node positions on every lookup. Needs O(n)
+/// memory instead of O(n²).
+#[derive(Debug, Clone)]
+pub struct Haversine {
+    /// (lat, lng) per node id.
+    positions: Vec<(f32, f32)>,
+}
+
+impl Haversine {
+    pub fn new(positions: Vec<(f32, f32)>) -> Self {
+        Self { positions }
+    }
+}
+
+impl Metric for Haversine {
+    fn cost(&self, from: usize, to: usize) -> f32 {
+        let (a, b) = (self.positions[from], self.positions[to]);
+        haversine_km(a.0 as f64, a.1 as f64, b.0 as f64, b.1 as f64) as f32
+    }
+
+    fn lazy(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_rows_should_keep_row_major_order() {
+        let matrix = DistanceMatrix::from_rows(vec![vec![0.0, 1.0], vec![2.0, 0.0]]).unwrap();
+        assert_eq!(matrix.len(), 2);
+        assert_eq!((matrix.cost(0, 1), matrix.cost(1, 0)), (1.0, 2.0));
+    }
+
+    #[test]
+    fn from_rows_should_reject_non_square_input() {
+        assert!(DistanceMatrix::from_rows(vec![vec![0.0, 1.0], vec![2.0]]).is_none());
+        assert!(DistanceMatrix::from_rows(vec![vec![0.0, 1.0]]).is_none());
+        assert!(DistanceMatrix::from_rows(Vec::new()).is_some_and(|m| m.is_empty()));
+    }
+
+    #[test]
+    fn from_fn_should_match_the_metric_it_caches() {
+        let haversine = Haversine::new(vec![(-33.45, -70.66), (-33.02, -71.55), (-36.82, -73.05)]);
+        let matrix = DistanceMatrix::from_fn(3, |a, b| haversine.cost(a, b));
+        for a in 0..3 {
+            for b in 0..3 {
+                assert_eq!(matrix.cost(a, b), haversine.cost(a, b));
+            }
+        }
+        assert!(haversine.lazy() && !matrix.lazy());
+    }
+}
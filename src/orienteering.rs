@@ -0,0 +1,281 @@
+use crate::tour::{Tour, EPS};
+use std::fmt;
+
+/// Rounds of re-ordering followed by re-inserting skipped stops.
+const MAX_ROUNDS: usize = 20;
+
+/// Stops kept and left out by `select`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    /// Node ids left off the route, in id order.
+    pub skipped: Vec<usize>,
+    /// Summed reward of the stops on the route.
+    pub reward: f32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OrienteeringError {
+    /// The leg between the fixed endpoints alone costs more than the limit.
+    BudgetTooSmall,
+}
+
+impl fmt::Display for OrienteeringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrienteeringError::BudgetTooSmall => write!(f, "The budget is too small for the fixed endpoints."),
+        }
+    }
+}
+
+/// Orienteering (prize-collecting): keeps the stops of `tour`'s route that collect the
+/// most `reward` while the route costs at most `limit`, in the tour's units, and leaves
+/// the rest out. The fixed endpoints always stay, so the route needs a fixed start.
+///
+/// Starts from the current route, so solve it first. Stops with the least reward per unit
+/// of cost saved are dropped until the route fits; then rounds of 2-opt and Or-opt
+/// alternate with inserting skipped stops, most reward per unit of added cost first,
+/// while they still fit. Stops with no reward are only kept if the whole route fits.
+/// Stops improving when the tour's budget runs out. Fails, leaving `tour` alone, when
+/// even the route between the endpoints costs more than `limit`.
+pub fn select(tour: &mut Tour, reward: &[f32], limit: f32) -> Result<Selection, OrienteeringError> {
+    assert_eq!(reward.len(), tour.nodes.len(), "Reward must have one entry per node");
+    let (start, end) = tour.endpoints();
+    assert!(start.is_some(), "Orienteering needs a fixed start");
+    let path: Vec<usize> = tour.route.iter().map(|n| n.id).collect();
+    let mut choice = Choice { tour, reward, limit: limit as f64, free_end: end.is_none(), path };
+    if choice.cost() > choice.limit + EPS {
+        while choice.cost() > choice.limit + EPS && choice.drop_one() {}
+        if choice.cost() > choice.limit + EPS {
+            return Err(OrienteeringError::BudgetTooSmall);
+        }
+        choice.reorder();
+    }
+    for _ in 0..MAX_ROUNDS {
+        if choice.tour.budget().exhausted() || !choice.insert_all() {
+            break;
+        }
+        choice.reorder();
+    }
+    let path = choice.path;
+
+    let mut on_route = vec![false; tour.nodes.len()];
+    for &id in &path {
+        on_route[id] = true;
+    }
+    tour.route = path.iter().map(|&id| tour.nodes[id].clone()).collect();
+    tour.calculate_cost();
+    Ok(Selection {
+        skipped: (0..tour.nodes.len()).filter(|&id| !on_route[id]).collect(),
+        reward: (0..tour.nodes.len()).filter(|&id| on_route[id]).map(|id| reward[id]).sum(),
+    })
+}
+
+/// Working state: `path` is the route as in `Tour::route`, endpoints included.
+struct Choice<'a> {
+    tour: &'a Tour,
+    reward: &'a [f32],
+    limit: f64,
+    /// The last stop is not fixed, so stops can be removed from or added after it.
+    free_end: bool,
+    path: Vec<usize>,
+}
+
+impl Choice<'_> {
+    fn c(&self, from: usize, to: usize) -> f64 {
+        self.tour.travel_cost(from, to) as f64
+    }
+
+    fn cost(&self) -> f64 {
+        self.path.windows(2).map(|w| self.c(w[0], w[1])).sum()
+    }
+
+    /// Positions of `path` that may be removed: everything but the fixed endpoints.
+    fn removable(&self) -> std::ops::Range<usize> {
+        1..if self.free_end { self.path.len() } else { self.path.len() - 1 }
+    }
+
+    /// Cost saved by removing the stop at position `i`.
+    fn saving(&self, i: usize) -> f64 {
+        let (prev, id) = (self.path[i - 1], self.path[i]);
+        match self.path.get(i + 1) {
+            Some(&next) => self.c(prev, id) + self.c(id, next) - self.c(prev, next),
+            None => self.c(prev, id),
+        }
+    }
+
+    /// Cost added by inserting `id` right after position `i`.
+    fn insertion(&self, id: usize, i: usize) -> f64 {
+        let prev = self.path[i];
+        match self.path.get(i + 1) {
+            Some(&next) => self.c(prev, id) + self.c(id, next) - self.c(prev, next),
+            None => self.c(prev, id),
+        }
+    }
+
+    /// Cheapest place for `id` as `(added cost, position to insert after)`.
+    fn best_insertion(&self, id: usize) -> (f64, usize) {
+        let last = if self.free_end { self.path.len() } else { self.path.len() - 1 };
+        (0..last)
+            .map(|i| (self.insertion(id, i), i))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .expect("the route has its start")
+    }
+
+    /// Drops the stop with the least reward per unit of cost saved. Returns `false` when
+    /// only the endpoints are left.
+    fn drop_one(&mut self) -> bool {
+        let worst = self.removable()
+            .map(|i| (self.reward[self.path[i]] as f64 / self.saving(i).max(EPS), i))
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        match worst {
+            Some((_, i)) => {
+                self.path.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Inserts skipped stops with a reward, most reward per unit of added cost first,
+    /// while any still fits. Returns whether one was inserted.
+    fn insert_all(&mut self) -> bool {
+        let mut on_route = vec![false; self.tour.nodes.len()];
+        for &id in &self.path {
+            on_route[id] = true;
+        }
+        let mut skipped: Vec<usize> = (0..self.tour.nodes.len())
+            .filter(|&id| !on_route[id] && self.reward[id] > 0.0)
+            .collect();
+        // Cheapest insertion per skipped stop, keyed by the stop it follows so positions
+        // can shift; only stops whose slot was taken need a full rescan
+        let mut best: Vec<(f64, usize)> = skipped.iter()
+            .map(|&id| {
+                let (added, i) = self.best_insertion(id);
+                (added, self.path[i])
+            })
+            .collect();
+        let mut cost = self.cost();
+        let mut inserted = false;
+        loop {
+            let pick = (0..skipped.len())
+                .filter(|&k| cost + best[k].0 <= self.limit + EPS)
+                .max_by(|&a, &b| {
+                    let ratio = |k: usize| self.reward[skipped[k]] as f64 / best[k].0.max(EPS);
+                    ratio(a).total_cmp(&ratio(b))
+                });
+            let Some(k) = pick else { break };
+            let (added, after) = best[k];
+            let id = skipped.swap_remove(k);
+            best.swap_remove(k);
+            let at = self.path.iter().position(|&x| x == after).expect("insertion follows a routed stop") + 1;
+            self.path.insert(at, id);
+            cost += added;
+            inserted = true;
+
+            for k in 0..skipped.len() {
+                if best[k].1 == after {
+                    let (added, i) = self.best_insertion(skipped[k]);
+                    best[k] = (added, self.path[i]);
+                } else {
+                    // Its slot survived; the new slots are either side of `id`
+                    for (i, from) in [(at - 1, after), (at, id)] {
+                        let added = self.insertion(skipped[k], i);
+                        if added < best[k].0 {
+                            best[k] = (added, from);
+                        }
+                    }
+                }
+            }
+        }
+        inserted
+    }
+
+    /// Re-orders the kept stops with 2-opt and Or-opt, keeping the endpoints.
+    fn reorder(&mut self) {
+        if let Some(path) = self.tour.reorder_path(&self.path) {
+            self.path = path;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tour::Strategy;
+
+    /// Closed tour from node 0 over stops on a line at `xs`, costing the gap between them.
+    fn line_tour(xs: &[f32]) -> Tour {
+        let matrix = xs.iter().map(|a| xs.iter().map(|b| (a - b).abs()).collect()).collect();
+        let mut tour = Tour::with_matrix(xs.iter().map(|&x| (x, 0.0)).collect(), matrix).unwrap();
+        tour.solve(&Strategy::named("fast").unwrap());
+        tour
+    }
+
+    #[test]
+    fn select_should_keep_every_stop_when_the_route_fits() {
+        let mut tour = line_tour(&[0.0, 1.0, 2.0, 3.0]);
+        let selection = select(&mut tour, &[0.0, 1.0, 2.0, 3.0], 10.0).unwrap();
+        assert!(selection.skipped.is_empty());
+        assert_eq!(selection.reward, 6.0);
+        assert_eq!(tour.cost, 6.0);
+    }
+
+    #[test]
+    fn select_should_drop_a_far_stop_with_little_reward() {
+        let mut tour = line_tour(&[0.0, 1.0, 2.0, 10.0]);
+        let selection = select(&mut tour, &[0.0, 5.0, 5.0, 1.0], 5.0).unwrap();
+        assert_eq!(selection, Selection { skipped: vec![3], reward: 10.0 });
+        assert_eq!(tour.cost, 4.0);
+        assert_eq!((tour.route[0].id, tour.route.last().unwrap().id), (0, 0));
+    }
+
+    #[test]
+    fn select_should_go_far_for_a_large_reward() {
+        let mut tour = line_tour(&[0.0, -1.0, -2.0, 5.0]);
+        let selection = select(&mut tour, &[0.0, 1.0, 1.0, 10.0], 10.0).unwrap();
+        assert_eq!(selection, Selection { skipped: vec![1, 2], reward: 10.0 });
+        assert!(tour.cost <= 10.0);
+    }
+
+    #[test]
+    fn select_should_not_add_stops_without_reward() {
+        let mut tour = line_tour(&[0.0, 1.0, 2.0, 3.0]);
+        let selection = select(&mut tour, &[0.0, 0.0, 1.0, 0.0], 5.0).unwrap();
+        assert_eq!(selection, Selection { skipped: vec![1, 3], reward: 1.0 });
+    }
+
+    #[test]
+    fn select_should_keep_fixed_endpoints_within_the_limit() {
+        let mut tour = Tour::create_random_nodes_with_seed(40, 0.5, 0.5, 7);
+        tour.set_endpoints(Some(0), Some(39));
+        tour.solve(&Strategy::named("fast").unwrap());
+        let limit = tour.cost / 2.0;
+        let reward: Vec<f32> = (0..40).map(|id| (id % 5) as f32).collect();
+        let selection = select(&mut tour, &reward, limit).unwrap();
+        assert!(tour.cost <= limit + 1e-4, "{} > {}", tour.cost, limit);
+        assert_eq!((tour.route[0].id, tour.route.last().unwrap().id), (0, 39));
+        assert!(!selection.skipped.is_empty());
+        assert_eq!(tour.route.len() + selection.skipped.len(), 40);
+        let collected: f32 = tour.route.iter().map(|n| reward[n.id]).sum();
+        assert_eq!(selection.reward, collected);
+    }
+
+    #[test]
+    fn select_should_fail_when_the_fixed_endpoints_exceed_the_limit() {
+        let mut tour = line_tour(&[0.0, 10.0, 4.0]);
+        tour.set_endpoints(Some(0), Some(1));
+        tour.solve(&Strategy::named("fast").unwrap());
+        assert_eq!(select(&mut tour, &[1.0; 3], 5.0), Err(OrienteeringError::BudgetTooSmall));
+        assert_eq!(tour.route.len(), 3);
+    }
+
+    #[test]
+    fn select_should_fill_a_free_end_up_to_the_limit() {
+        let mut tour = line_tour(&[0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        tour.set_endpoints(Some(0), None);
+        tour.solve(&Strategy::named("fast").unwrap());
+        let selection = select(&mut tour, &[1.0; 6], 3.0).unwrap();
+        assert_eq!(selection, Selection { skipped: vec![4, 5], reward: 4.0 });
+        assert_eq!(tour.cost, 3.0);
+    }
+}
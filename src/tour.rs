@@ -1,6 +1,40 @@
+use crate::genetic::{self, Genetic};
+use crate::metric::{DistanceMatrix, Haversine, Metric};
 use rand::prelude::SliceRandom;
-use rand::Rng;
-use std::collections::HashMap;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Minimum change in cost that counts as an improvement, for every solver.
+pub(crate) const EPS: f64 = 1e-6;
+/// Size of the per-node candidate lists used by Lin-Kernighan.
+const LK_CANDIDATES: usize = 8;
+/// Largest tour whose lazy metric `distance_matrix` caches (4 bytes per entry: 64 MB).
+const DENSE_MAX_NODES: usize = 4000;
+/// Nearest neighbours per node that 2-opt and Or-opt try as new edge endpoints.
+const NEIGHBOUR_CANDIDATES: usize = 10;
+/// Maximum number of flips chained into one Lin-Kernighan move.
+const LK_MAX_DEPTH: usize = 12;
+/// Alternatives tried at each of the first levels before the search narrows to the best
+/// candidate only. A chain of four flips is a sequential 5-opt move, so the first levels
+/// keep some breadth to explore those.
+const LK_BREADTH: [usize; 4] = [5, 3, 2, 2];
+/// Largest cycle solved exactly with Held-Karp (O(2^n · n²) time, O(2^n · n) memory).
+const HELD_KARP_MAX_NODES: usize = 15;
+/// Largest cycle `solve_exact` attempts with branch and bound.
+const BRANCH_AND_BOUND_MAX_NODES: usize = 25;
+/// Search nodes branch and bound may expand before giving up on proving optimality.
+const BRANCH_AND_BOUND_MAX_EXPANSIONS: usize = 500_000;
+/// Largest cycle `lower_bound` bounds; it needs a dense m × m matrix of f64 costs.
+const LOWER_BOUND_MAX_NODES: usize = 500;
+/// Subgradient steps taken by `lower_bound`.
+const LOWER_BOUND_ITERATIONS: usize = 100;
+/// Cap on `iterations × nodes²` for `lower_bound`, so large instances take fewer steps.
+const LOWER_BOUND_WORK: usize = 100_000_000;
 
 #[derive(Debug, Clone)]
 pub struct Node {
@@ -9,82 +43,720 @@ pub struct Node {
     pub y: f32,
 }
 
-#[derive(Debug)]
+/// Opening hours of a stop, in seconds on the same clock as the departure time.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeWindow {
+    pub earliest: f32,
+    pub latest: f32,
+}
+
+/// Why a set of visiting-order constraints cannot be met by any route.
+#[derive(Debug, PartialEq)]
+pub enum OrderError {
+    /// The constraints require each stop to come before the next one, all the way round.
+    Cycle(Vec<usize>),
+    /// A stop must be visited before the route's fixed first stop.
+    BeforeStart { stop: usize, start: usize },
+    /// A stop must be visited after the route's fixed last stop.
+    AfterEnd { stop: usize, end: usize },
+    /// The route's fixed first or last stop was pinned too.
+    PinnedEndpoint(usize),
+    /// Positions run from 1 to the number of stops between the route's ends.
+    PinOutOfRange { stop: usize, position: usize, stops: usize },
+    /// One stop pinned to two positions, or two stops to one position.
+    PinClash { stop: usize, position: usize },
+    /// No route meets both this pin and the precedence constraints.
+    PinConflict { stop: usize, position: usize },
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderError::Cycle(stops) => {
+                let path: Vec<String> = stops.iter().chain(stops.first()).map(|id| id.to_string()).collect();
+                write!(f, "Precedence constraints form a cycle: {}.", path.join(" → "))
+            }
+            OrderError::BeforeStart { stop, start } => {
+                write!(f, "Stop {} cannot come before the route's start, stop {}.", stop, start)
+            }
+            OrderError::AfterEnd { stop, end } => {
+                write!(f, "Stop {} cannot come after the route's end, stop {}.", stop, end)
+            }
+            OrderError::PinnedEndpoint(stop) => {
+                write!(f, "Stop {} is the route's start or end and cannot be pinned.", stop)
+            }
+            OrderError::PinOutOfRange { stop, position, stops } => {
+                write!(f, "Stop {} cannot be pinned to position {}: positions run from 1 to {}.", stop, position, stops)
+            }
+            OrderError::PinClash { stop, position } => {
+                write!(f, "Stop {} cannot be pinned to position {}: another pin claims it.", stop, position)
+            }
+            OrderError::PinConflict { stop, position } => {
+                write!(f, "Stop {} cannot be at position {} and meet the precedence constraints.", stop, position)
+            }
+        }
+    }
+}
+
+/// Why a distance matrix cannot be used for a set of positions.
+#[derive(Debug, PartialEq)]
+pub enum MatrixError {
+    /// Some row has a different length than the number of rows.
+    NotSquare,
+    /// The matrix is square but has a different number of rows than there are positions.
+    SizeMismatch { rows: usize, positions: usize },
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatrixError::NotSquare => write!(f, "Distance matrix must be square."),
+            MatrixError::SizeMismatch { rows, positions } => {
+                write!(f, "Distance matrix has {} rows for {} positions.", rows, positions)
+            }
+        }
+    }
+}
+
+/// Constraints on the order a route visits its stops in: "A before B" pairs and stops
+/// pinned to a position, checked against each other and the route's endpoints. See
+/// `Tour::set_order_constraints`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OrderConstraints {
+    /// Endpoints the constraints were checked against (see `Tour::set_endpoints`).
+    endpoints: (Option<usize>, Option<usize>),
+    /// `(before, after)` pairs that still constrain the order.
+    precedence: Vec<(usize, usize)>,
+    /// `(stop, position)` pairs; position 1 is the first stop after the start.
+    pins: Vec<(usize, usize)>,
+    /// Latest position each stop can take and still let every pin be met, by node id.
+    /// Empty without pins.
+    deadline: Vec<usize>,
+}
+
+impl OrderConstraints {
+    /// Checks `(before, after)` pairs and `(stop, position)` pins for a route over `n`
+    /// nodes between `start` and `end`, where position 1 is the first stop after `start`.
+    /// Pairs every route meets, such as `start` before any stop, are dropped. A group of
+    /// stops that must keep their relative order is a chain of pairs.
+    pub fn new(
+        n: usize,
+        start: Option<usize>,
+        end: Option<usize>,
+        precedence: &[(usize, usize)],
+        pins: &[(usize, usize)],
+    ) -> Result<Self, OrderError> {
+        assert!(precedence.iter().all(|&(a, b)| a < n && b < n) && pins.iter().all(|&(id, _)| id < n),
+            "Order constraints must refer to valid node ids");
+        let precedence = check_precedence(precedence, start, end)?;
+        let stops: Vec<usize> = (0..n).filter(|&id| Some(id) != start && Some(id) != end).collect();
+        let mut slots = vec![None; stops.len() + 1];
+        let mut kept: Vec<(usize, usize)> = Vec::new();
+        for &(stop, position) in pins {
+            if Some(stop) == start || Some(stop) == end {
+                return Err(OrderError::PinnedEndpoint(stop));
+            }
+            if position == 0 || position > stops.len() {
+                return Err(OrderError::PinOutOfRange { stop, position, stops: stops.len() });
+            }
+            match slots[position] {
+                Some(other) if other == stop => continue,
+                Some(_) => return Err(OrderError::PinClash { stop, position }),
+                None if kept.iter().any(|&(s, _)| s == stop) => return Err(OrderError::PinClash { stop, position }),
+                None => {}
+            }
+            slots[position] = Some(stop);
+            kept.push((stop, position));
+        }
+
+        let mut constraints = Self { endpoints: (start, end), precedence, pins: kept, deadline: Vec::new() };
+        if !constraints.pins.is_empty() {
+            constraints.deadline = constraints.deadlines(n, stops.len());
+            // Deadline first meets every pin whenever some order can
+            constraints.schedule(n, &stops, true, |_, ready| ready[0])?;
+        }
+        Ok(constraints)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.precedence.is_empty() && self.pins.is_empty()
+    }
+
+    /// Successors and number of predecessors of every node id below `size`.
+    fn graph(&self, size: usize) -> (Vec<Vec<usize>>, Vec<usize>) {
+        let mut succs = vec![Vec::new(); size];
+        let mut waiting = vec![0; size];
+        for &(a, b) in &self.precedence {
+            succs[a].push(b);
+            waiting[b] += 1;
+        }
+        (succs, waiting)
+    }
+
+    /// A stop's pinned position or the last one, tightened so each stop leaves room for
+    /// everything that must follow it before a pin.
+    fn deadlines(&self, n: usize, stops: usize) -> Vec<usize> {
+        let mut deadline = vec![stops; n];
+        for &(stop, position) in &self.pins {
+            deadline[stop] = position;
+        }
+        let (succs, mut waiting) = self.graph(n);
+        let mut ready: Vec<usize> = (0..n).filter(|&id| waiting[id] == 0).collect();
+        let mut topological = Vec::with_capacity(n);
+        while let Some(id) = ready.pop() {
+            topological.push(id);
+            for &next in &succs[id] {
+                waiting[next] -= 1;
+                if waiting[next] == 0 {
+                    ready.push(next);
+                }
+            }
+        }
+        for &id in topological.iter().rev() {
+            for &next in &succs[id] {
+                deadline[id] = deadline[id].min(deadline[next].saturating_sub(1));
+            }
+        }
+        deadline
+    }
+
+    /// Lays `stops` out in positions 1, 2, … so every constraint holds. Pinned positions
+    /// take their stop; every other one the stop `choose(previous, ready)` picks from those
+    /// whose predecessors are all placed, narrowed to the most urgent deadline when
+    /// `by_deadline`. Without it the choice is freer but can strand a later pin.
+    fn schedule(
+        &self,
+        previous: usize,
+        stops: &[usize],
+        by_deadline: bool,
+        mut choose: impl FnMut(usize, &[usize]) -> usize,
+    ) -> Result<Vec<usize>, OrderError> {
+        let size = stops.iter().max().map_or(0, |&m| m + 1).max(self.deadline.len());
+        let (succs, mut waiting) = self.graph(size);
+        let mut pinned_at = vec![None; stops.len() + 1];
+        let mut pinned = vec![false; size];
+        for &(stop, position) in &self.pins {
+            pinned_at[position] = Some(stop);
+            pinned[stop] = true;
+        }
+        let deadline = |id: usize| self.deadline.get(id).copied().unwrap_or(stops.len());
+        let mut ready: Vec<usize> = stops.iter().copied().filter(|&id| waiting[id] == 0 && !pinned[id]).collect();
+        let mut laid = Vec::with_capacity(stops.len());
+        let mut previous = previous;
+        for (position, &pin) in pinned_at.iter().enumerate().skip(1) {
+            let id = match pin {
+                Some(id) if waiting[id] == 0 => id,
+                Some(id) => return Err(OrderError::PinConflict { stop: id, position }),
+                None => {
+                    let urgent = ready.iter().map(|&id| deadline(id)).min();
+                    let id = match urgent {
+                        Some(d) if d < position => None,
+                        Some(d) if by_deadline => {
+                            let most_urgent: Vec<usize> = ready.iter().copied().filter(|&id| deadline(id) == d).collect();
+                            Some(choose(previous, &most_urgent))
+                        }
+                        Some(_) => Some(choose(previous, &ready)),
+                        None => None,
+                    };
+                    let Some(id) = id else {
+                        // Only pins make an acyclic order fail; blame the next one
+                        let &(stop, position) = self.pins.iter()
+                            .filter(|&&(_, p)| p >= position)
+                            .min_by_key(|&&(_, p)| p)
+                            .or(self.pins.first())
+                            .expect("only pins can make scheduling fail");
+                        return Err(OrderError::PinConflict { stop, position });
+                    };
+                    let i = ready.iter().position(|&r| r == id).expect("choose picks a ready stop");
+                    ready.swap_remove(i);
+                    id
+                }
+            };
+            laid.push(id);
+            previous = id;
+            for &next in &succs[id] {
+                waiting[next] -= 1;
+                if waiting[next] == 0 && !pinned[next] {
+                    ready.push(next);
+                }
+            }
+        }
+        Ok(laid)
+    }
+}
+
+/// Checks `(before, after)` pairs against each other and against the route's fixed
+/// endpoints. Returns the pairs that still constrain the order.
+fn check_precedence(
+    pairs: &[(usize, usize)],
+    start: Option<usize>,
+    end: Option<usize>,
+) -> Result<Vec<(usize, usize)>, OrderError> {
+    let closed = start.is_some() && start == end;
+    let mut kept = Vec::new();
+    for &(before, after) in pairs {
+        if before == after {
+            return Err(OrderError::Cycle(vec![before]));
+        }
+        if Some(after) == start {
+            return Err(OrderError::BeforeStart { stop: before, start: after });
+        }
+        if Some(before) == start {
+            continue;
+        }
+        if Some(before) == end && !closed {
+            return Err(OrderError::AfterEnd { stop: after, end: before });
+        }
+        if Some(after) == end {
+            continue;
+        }
+        kept.push((before, after));
+    }
+
+    // Kahn's algorithm: whatever cannot be ordered lies on or behind a cycle
+    let size = kept.iter().map(|&(a, b)| a.max(b) + 1).max().unwrap_or(0);
+    let mut preds = vec![Vec::new(); size];
+    let mut succs = vec![Vec::new(); size];
+    for &(a, b) in &kept {
+        succs[a].push(b);
+        preds[b].push(a);
+    }
+    let mut waiting: Vec<usize> = preds.iter().map(Vec::len).collect();
+    let mut ready: Vec<usize> = (0..size).filter(|&id| waiting[id] == 0).collect();
+    while let Some(id) = ready.pop() {
+        for &next in &succs[id] {
+            waiting[next] -= 1;
+            if waiting[next] == 0 {
+                ready.push(next);
+            }
+        }
+    }
+    if let Some(stuck) = (0..size).find(|&id| waiting[id] > 0) {
+        // Every stuck stop has a stuck predecessor, so walking back must repeat one
+        let mut seen = vec![usize::MAX; size];
+        let mut walk = vec![stuck];
+        let mut id = stuck;
+        while seen[id] == usize::MAX {
+            seen[id] = walk.len() - 1;
+            id = *preds[id].iter().find(|&&p| waiting[p] > 0).expect("stuck stops have a stuck predecessor");
+            walk.push(id);
+        }
+        let mut cycle = walk[seen[id]..walk.len() - 1].to_vec();
+        cycle.reverse();
+        return Err(OrderError::Cycle(cycle));
+    }
+    Ok(kept)
+}
+
+/// Timing of one stop along the route, as computed by `Tour::schedule`.
+#[derive(Debug, Clone)]
+pub struct StopTime {
+    pub id: usize,
+    pub arrival: f32,
+    /// Time spent waiting for the window to open.
+    pub wait: f32,
+    /// When the vehicle leaves, after waiting and the service time.
+    pub departure: f32,
+    /// How far past the window's `latest` the vehicle arrived (0 when on time).
+    pub late: f32,
+}
+
+/// How the first route is built before any improvement phase runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Construction {
+    NearestNeighbour,
+    Random,
+    CheapestInsertion,
+    FarthestInsertion,
+    ConvexHullInsertion,
+    GreedyEdge,
+    SpaceFillingCurve,
+    Christofides,
+}
+
+impl Construction {
+    /// Names accepted by `Construction::named`.
+    pub const NAMES: [&'static str; 8] = [
+        "nearest_neighbour", "random", "cheapest_insertion", "farthest_insertion",
+        "convex_hull", "greedy_edge", "space_filling_curve", "christofides",
+    ];
+
+    pub fn named(name: &str) -> Option<Self> {
+        use Construction::*;
+        let all = [
+            NearestNeighbour, Random, CheapestInsertion, FarthestInsertion,
+            ConvexHullInsertion, GreedyEdge, SpaceFillingCurve, Christofides,
+        ];
+        Self::NAMES.iter().position(|&n| n == name).map(|i| all[i])
+    }
+}
+
+/// An improvement phase applied to an existing route.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Improvement {
+    TwoOpt,
+    OrOpt,
+    LinKernighan,
+    ThreeOpt,
+    Annealing(Annealing),
+    /// Double-bridge kicks, each followed by 2-opt and Or-opt.
+    IteratedLocalSearch { kicks: usize },
+    /// Memetic genetic algorithm; see `genetic::evolve`.
+    Genetic(Genetic),
+    AntColony(AntColony),
+}
+
+/// Schedule for `Tour::simulated_annealing`. Temperatures are fractions of the route's
+/// mean edge cost, so one schedule suits both kilometres and seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annealing {
+    /// Random moves tried.
+    pub iterations: usize,
+    pub initial_temperature: f64,
+    /// Temperature reached on the last move; cooling is geometric in between.
+    pub final_temperature: f64,
+}
+
+impl Default for Annealing {
+    fn default() -> Self {
+        Self { iterations: 1_000_000, initial_temperature: 0.3, final_temperature: 0.001 }
+    }
+}
+
+/// Settings for `Tour::ant_colony`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AntColony {
+    /// Rounds in which every ant builds one route.
+    pub iterations: usize,
+    pub ants: usize,
+}
+
+impl Default for AntColony {
+    fn default() -> Self {
+        Self { iterations: 100, ants: 10 }
+    }
+}
+
+/// Nearest stops an ant weighs at each step before falling back to every unvisited stop.
+/// At least `NEIGHBOUR_CANDIDATES`, as the colony's 2-opt takes its lists from these.
+const ANT_CANDIDATES: usize = 15;
+/// Probability that an ant takes the best-looking arc instead of sampling one.
+const ANT_EXPLOITATION: f64 = 0.9;
+/// Share of pheromone that evaporates in the local and global updates.
+const ANT_EVAPORATION: f64 = 0.1;
+
+/// Kicks per `Improvement::IteratedLocalSearch` in the `ils` preset.
+const ILS_KICKS: usize = 200;
+
+/// A construction heuristic followed by improvement phases, run by `Tour::solve`.
+#[derive(Debug, Clone)]
+pub struct Strategy {
+    pub construction: Construction,
+    pub improvements: Vec<Improvement>,
+    /// Repeat the improvement phases until a whole pass no longer lowers the cost.
+    pub until_stable: bool,
+}
+
+impl Strategy {
+    /// Names accepted by `Strategy::named`: the local-search presets fastest first, then
+    /// the metaheuristics.
+    pub const NAMES: [&'static str; 7] = ["fast", "balanced", "best", "annealing", "ils", "genetic", "ants"];
+
+    /// Preset strategies: `fast` for interactive use, `balanced` (the default) and
+    /// `best`, which keeps cycling its phases for as long as they find improvements.
+    /// `annealing`, `ils` and `ants` escape the local optimum 2-opt and Or-opt stop in,
+    /// and `genetic` trades much more time for the best routes.
+    pub fn named(name: &str) -> Option<Self> {
+        use Improvement::*;
+        let (improvements, until_stable) = match name {
+            "fast" => (vec![TwoOpt], false),
+            "balanced" => (vec![TwoOpt, OrOpt, ThreeOpt, LinKernighan], false),
+            "best" => (vec![TwoOpt, OrOpt, ThreeOpt, LinKernighan], true),
+            "annealing" => (vec![TwoOpt, OrOpt, Annealing(Default::default()), TwoOpt, OrOpt], false),
+            "ils" => (vec![TwoOpt, OrOpt, IteratedLocalSearch { kicks: ILS_KICKS }], false),
+            "genetic" => (vec![TwoOpt, OrOpt, Genetic(Default::default())], false),
+            "ants" => (vec![TwoOpt, AntColony(Default::default()), OrOpt], false),
+            _ => return None,
+        };
+        Some(Self { construction: Construction::NearestNeighbour, improvements, until_stable })
+    }
+
+    /// Overrides the metaheuristic phases' iteration count (annealing moves, ILS kicks,
+    /// genetic generations or colony iterations) and starting annealing temperature.
+    pub fn tune(&mut self, iterations: Option<usize>, temperature: Option<f64>) {
+        for improvement in self.improvements.iter_mut() {
+            match improvement {
+                Improvement::Annealing(schedule) => {
+                    if let Some(n) = iterations {
+                        schedule.iterations = n;
+                    }
+                    if let Some(t) = temperature {
+                        schedule.initial_temperature = t;
+                    }
+                }
+                Improvement::IteratedLocalSearch { kicks } => {
+                    if let Some(n) = iterations {
+                        *kicks = n;
+                    }
+                }
+                Improvement::Genetic(settings) => {
+                    if let Some(n) = iterations {
+                        settings.generations = n;
+                    }
+                }
+                Improvement::AntColony(colony) => {
+                    if let Some(n) = iterations {
+                        colony.iterations = n;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Default for Strategy {
+    fn default() -> Self {
+        Self::named("balanced").expect("balanced is a preset")
+    }
+}
+
+/// Limits how long the improvement loops may run: a wall-clock deadline and/or a flag
+/// another thread can raise to cancel. Loops only stop between moves, so the tour is
+/// always complete and never worse than when the loop started.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    deadline: Option<Instant>,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Budget {
+    pub fn until(deadline: Instant) -> Self {
+        Self { deadline: Some(deadline), cancel: None }
+    }
+
+    pub fn with_cancel(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    pub fn exhausted(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed))
+            || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Tour {
     pub nodes: Vec<Node>,
     pub route: Vec<Node>,
     pub cost: f32,
-    pub distance: Vec<Vec<Option<f32>>>,
+    /// Travel costs by node id, shared between clones (e.g. multi-start workers); see
+    /// `distance_matrix`.
+    pub distance: Arc<dyn Metric>,
+    /// Source of randomness for every randomized step; see `set_seed`.
+    rng: StdRng,
+    /// Fixed first stop of the route, if any.
+    start: Option<usize>,
+    /// Fixed last stop of the route, if any. `start == end` is a closed loop.
+    end: Option<usize>,
+    /// Node that closes the cycle the solvers work on; see `set_endpoints`.
+    depot: usize,
+    /// Per-node time windows; empty when the tour has none. See `set_time_windows`.
+    windows: Vec<Option<TimeWindow>>,
+    /// Per-node service duration, same units as the matrix.
+    service: Vec<f32>,
+    /// Time the route leaves its first stop.
+    departure: f32,
+    /// Order the stops must be visited in; see `set_order_constraints`.
+    constraints: OrderConstraints,
+    /// Time allowed to the improvement loops; unlimited by default.
+    budget: Budget,
 }
 
 impl Tour {
     pub fn new(positions: Vec<(f32, f32)>) -> Self {
-        assert!(!positions.is_empty(), "Tour requires at least one node");
-        let distance = vec![vec![None; positions.len()]; positions.len()];
-        let mut nodes: Vec<Node> = Vec::new();
-        let mut route: Vec<Node> = Vec::new();
-        for (id, (x, y)) in positions.iter().enumerate() {
-            route.push(Node { id, x: *x, y: *y });
-            nodes.push(Node { id, x: *x, y: *y });
-        }
-        route.push(route[0].clone());
-        Self { route, nodes, cost: 0.0, distance }
+        let metric = Arc::new(Haversine::new(positions.clone()));
+        Self::with_metric(positions, metric)
     }
 
     /// Constructs a Tour with a pre-built NxN distance/time matrix.
     /// Use this when distances come from an external source (e.g. OSRM road times in seconds).
     /// The matrix bypasses haversine entirely — all solver algorithms use it directly.
-    pub fn with_matrix(positions: Vec<(f32, f32)>, matrix: Vec<Vec<f32>>) -> Self {
-        assert!(!positions.is_empty(), "Tour requires at least one node");
-        let n = positions.len();
-        let distance = matrix.iter()
-            .map(|row| row.iter().map(|&v| Some(v)).collect())
-            .collect();
-        let mut nodes = Vec::with_capacity(n);
-        let mut route = Vec::with_capacity(n + 1);
-        for (id, &(x, y)) in positions.iter().enumerate() {
-            nodes.push(Node { id, x, y });
-            route.push(Node { id, x, y });
+    /// Fails unless the matrix is square with one row per position.
+    pub fn with_matrix(positions: Vec<(f32, f32)>, matrix: Vec<Vec<f32>>) -> Result<Self, MatrixError> {
+        let matrix = DistanceMatrix::from_rows(matrix).ok_or(MatrixError::NotSquare)?;
+        if matrix.len() != positions.len() {
+            return Err(MatrixError::SizeMismatch { rows: matrix.len(), positions: positions.len() });
         }
-        route.push(route[0].clone());
-        Self { route, nodes, cost: 0.0, distance }
+        Ok(Self::with_metric(positions, Arc::new(matrix)))
+    }
+
+    /// Constructs a Tour whose distances come from any `Metric`, indexed by position.
+    pub fn with_metric(positions: Vec<(f32, f32)>, distance: Arc<dyn Metric>) -> Self {
+        Self::from_parts(positions, distance, StdRng::from_entropy())
     }
 
     pub fn create_random_nodes(n: usize, width: f32, height: f32) -> Self {
-        let mut rng = rand::thread_rng();
-        let mut nodes: Vec<Node> = Vec::new();
-        let mut route: Vec<Node> = Vec::new();
-        for id in 0..n {
-            let x = rng.gen::<f32>() * width;
-            let y = rng.gen::<f32>() * height;
-            route.push(Node {id, x, y });
-            nodes.push(Node {id, x, y });
-        }
+        Self::random_nodes_from(StdRng::from_entropy(), n, width, height)
+    }
+
+    /// Same as `create_random_nodes`, but the positions and every later randomized step
+    /// are reproducible for a given seed.
+    pub fn create_random_nodes_with_seed(n: usize, width: f32, height: f32, seed: u64) -> Self {
+        Self::random_nodes_from(StdRng::seed_from_u64(seed), n, width, height)
+    }
+
+    fn random_nodes_from(mut rng: StdRng, n: usize, width: f32, height: f32) -> Self {
+        let positions: Vec<(f32, f32)> = (0..n)
+            .map(|_| (rng.gen::<f32>() * width, rng.gen::<f32>() * height))
+            .collect();
+        let metric = Arc::new(Haversine::new(positions.clone()));
+        Self::from_parts(positions, metric, rng)
+    }
+
+    fn from_parts(positions: Vec<(f32, f32)>, distance: Arc<dyn Metric>, rng: StdRng) -> Self {
+        assert!(!positions.is_empty(), "Tour requires at least one node");
+        let n = positions.len();
+        let nodes: Vec<Node> = positions.iter().enumerate().map(|(id, &(x, y))| Node { id, x, y }).collect();
+        let mut route = nodes.clone();
         route.push(route[0].clone());
-        let distance = vec![vec![None; nodes.len()]; nodes.len()];
-        Self { nodes, route, cost: 0.0, distance }
+        Self {
+            route, nodes, cost: 0.0, distance, rng,
+            start: Some(0), end: Some(0), depot: 0,
+            windows: Vec::new(), service: vec![0.0; n], departure: 0.0, constraints: OrderConstraints::default(),
+            budget: Budget::default(),
+        }
     }
 
-    fn distance(&self, node_1: &Node, node_2: &Node) -> f32 {
-        if let Some(d) = self.distance[node_1.id][node_2.id] {
-            return d;
+    /// The tour's RNG, for randomized steps implemented outside this module.
+    pub(crate) fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    /// Reseeds the tour's RNG so randomized steps (e.g. `random_tour`) are reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Chooses which stops the route starts and ends at. `start == end` (the default, node 0)
+    /// is a closed loop; anything else is an open path, with `None` leaving that end free.
+    /// Resets `route` to visit the remaining nodes in id order.
+    ///
+    /// Internally every variant is solved as a cycle: an open path is closed through a
+    /// depot node whose incoming edges cost the same as reaching `end` (zero when free) and
+    /// whose outgoing edges cost the same as leaving `start` (zero when free). When both
+    /// ends are free the depot is a virtual node with id `nodes.len()`. Clears the order
+    /// constraints, which depend on the endpoints.
+    pub fn set_endpoints(&mut self, start: Option<usize>, end: Option<usize>) {
+        let n = self.nodes.len();
+        assert!(start.is_none_or(|s| s < n) && end.is_none_or(|e| e < n),
+            "Route endpoints must be valid node ids");
+        self.start = start;
+        self.end = end;
+        self.depot = start.or(end).unwrap_or(n);
+        self.constraints = OrderConstraints::default();
+        let order: Vec<usize> = std::iter::once(self.depot)
+            .chain((0..n).filter(|&id| Some(id) != start && Some(id) != end))
+            .collect();
+        self.set_order(order);
+    }
+
+    /// Attaches per-node time windows (`None` = always open) and service durations, plus
+    /// the time the route leaves its first stop. All values share the matrix's units, so
+    /// they are only meaningful with a travel-time matrix such as OSRM durations.
+    pub fn set_time_windows(&mut self, windows: Vec<Option<TimeWindow>>, service: Vec<f32>, departure: f32) {
+        assert!(windows.len() == self.nodes.len() && service.len() == self.nodes.len(),
+            "Time windows and service times must have one entry per node");
+        self.windows = windows;
+        self.service = service;
+        self.departure = departure;
+    }
+
+    /// Restricts the order stops are visited in, e.g. a parcel's pickup before its
+    /// delivery or a stop pinned first. Call after `set_endpoints`, with constraints
+    /// checked against the same endpoints.
+    ///
+    /// Nearest neighbour builds a route that meets them, and the improvement phases start
+    /// by repairing any other route into one. 2-opt, Or-opt, iterated local search and the
+    /// time-window search only make moves that keep them; any other phase keeps its result
+    /// only when it happens to.
+    pub fn set_order_constraints(&mut self, constraints: OrderConstraints) {
+        assert_eq!(constraints.endpoints, (self.start, self.end),
+            "Order constraints were checked for other endpoints");
+        self.constraints = constraints;
+    }
+
+    /// Stops every improvement loop once `budget` runs out, keeping the best tour so far.
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.budget = budget;
+    }
+
+    pub fn budget(&self) -> &Budget {
+        &self.budget
+    }
+
+    /// Cost of travelling `from → to` between two real nodes.
+    pub fn travel_cost(&self, from: usize, to: usize) -> f32 {
+        self.distance(&self.nodes[from], &self.nodes[to])
+    }
+
+    /// A closed tour over `ids`, visiting them in the given order starting at `ids[0]`,
+    /// with the same distances and budget as this tour. Node `k` of the new tour is
+    /// `ids[k]` here.
+    pub fn subtour(&self, ids: &[usize]) -> Tour {
+        let positions = ids.iter().map(|&id| (self.nodes[id].x, self.nodes[id].y)).collect();
+        let matrix = DistanceMatrix::from_fn(ids.len(), |a, b| self.travel_cost(ids[a], ids[b]));
+        let mut sub = Tour::with_metric(positions, Arc::new(matrix));
+        sub.set_budget(self.budget.clone());
+        sub
+    }
+
+    /// Re-orders `path`, a route over some of the nodes laid out like `route`, with 2-opt
+    /// and Or-opt on a `subtour`, keeping this tour's fixed endpoints in place. Returns the
+    /// new path, or `None` when it is no cheaper.
+    pub(crate) fn reorder_path(&self, path: &[usize]) -> Option<Vec<usize>> {
+        let closed = self.is_closed() && path.len() > 1;
+        let ids = if closed { &path[..path.len() - 1] } else { path };
+        if ids.len() < 4 {
+            return None;
         }
-        if let Some(d) = self.distance[node_2.id][node_1.id] {
-            return d;
+        let mut sub = self.subtour(ids);
+        if !closed {
+            // Subtour ids follow the path, so resetting the endpoints keeps the order
+            sub.set_endpoints(self.start.map(|_| 0), self.end.map(|_| ids.len() - 1));
         }
-        haversine_km(node_1.x as f64, node_1.y as f64,
-                     node_2.x as f64, node_2.y as f64) as f32
+        sub.two_opt();
+        sub.or_opt();
+        let reordered: Vec<usize> = sub.route.iter().map(|n| ids[n.id]).collect();
+        let cost = |p: &[usize]| p.windows(2).map(|w| self.travel_cost(w[0], w[1]) as f64).sum::<f64>();
+        (cost(&reordered) < cost(path) - EPS).then_some(reordered)
     }
 
+    /// The fixed first and last stops; see `set_endpoints`.
+    pub fn endpoints(&self) -> (Option<usize>, Option<usize>) {
+        (self.start, self.end)
+    }
+
+    /// Whether the route returns to its first stop.
+    pub fn is_closed(&self) -> bool {
+        self.start.is_some() && self.start == self.end
+    }
+
+    fn distance(&self, node_1: &Node, node_2: &Node) -> f32 {
+        self.distance.cost(node_1.id, node_2.id)
+    }
+
+    /// Caches a lazy metric (e.g. haversine) in a dense `DistanceMatrix`, unless there are
+    /// more than `DENSE_MAX_NODES` nodes, where it stays computed on demand to bound
+    /// memory. A stored matrix is never copied, so clones made after this call share it.
     pub fn distance_matrix(&mut self) {
-        for node_1 in self.nodes.iter() {
-            for node_2 in self.nodes.iter() {
-                if self.distance[node_1.id][node_2.id].is_none() {
-                    let d = self.distance(node_1, node_2);
-                    self.distance[node_1.id][node_2.id] = Some(d);
-                }
-            }
+        let n = self.nodes.len();
+        if !self.distance.lazy() || n > DENSE_MAX_NODES {
+            return;
         }
+        let matrix = DistanceMatrix::from_fn(n, |a, b| self.distance.cost(a, b));
+        self.distance = Arc::new(matrix);
     }
 
     pub fn calculate_cost(&mut self) {
@@ -96,532 +768,3380 @@ impl Tour {
     }
 
     pub fn random_tour(&mut self) {
-        let mut route: Vec<Node> = self.nodes[1..].to_vec();
-        route.shuffle(&mut rand::thread_rng());
-        self.route = vec![self.nodes[0].clone()];
-        self.route.extend(route);
-        self.route.push(self.nodes[0].clone());
+        let mut order = self.order();
+        order[1..].shuffle(&mut self.rng);
+        self.set_order(order);
     }
 
     pub fn nearest_neighbour_tour(&mut self) {
+        if self.constraints.is_empty() {
+            self.nearest_neighbour_from(self.depot);
+            return;
+        }
+        // Nearest of the stops the order constraints allow next
+        self.distance_matrix();
+        let order = self.lay_out(|previous, ready| {
+            *ready.iter()
+                .min_by(|&&x, &&y| self.arc(previous, x).partial_cmp(&self.arc(previous, y)).unwrap_or(std::cmp::Ordering::Equal))
+                .expect("ready is not empty")
+        });
+        self.set_order(order);
+    }
+
+    /// Nearest neighbour starting at `first` instead of the depot; the cycle is then
+    /// rotated back so the depot leads.
+    fn nearest_neighbour_from(&mut self, first: usize) {
         self.distance_matrix();
-        let mut current_index = self.nodes[0].id;
-        let mut route = vec![self.nodes[0].clone()];
-        let mut visited: HashMap<usize, bool> = HashMap::new();
-        for node in self.nodes.iter() {
-            visited.insert(node.id, false);
-        }
-        visited.insert(current_index, true);
-        for _ in 0..self.nodes.len() - 1 {
-            let mut dist: Vec<f32> = self.distance[current_index]
+        let ids = self.order();
+        let mut current_index = first;
+        let mut order = vec![current_index];
+        // Indexed by id; a plain vector keeps this fast on thousands of stops
+        let mut visited = vec![false; ids.iter().max().map_or(0, |&m| m + 1)];
+        visited[current_index] = true;
+        for _ in 0..ids.len() - 1 {
+            let min_index = ids
                 .iter()
-                .map(|d| d.unwrap_or(f32::MAX))
-                .collect();
-            for i in 0..self.nodes.len() {
-                if visited[&i] {
-                    dist[i] = f32::MAX;
-                }
-            }
-            let min_index = dist.iter().enumerate().min_by(|x, y| x.1.partial_cmp(y.1).unwrap_or(std::cmp::Ordering::Equal)).unwrap().0;
-            route.push(self.nodes[min_index].clone());
+                .copied()
+                .filter(|&id| !visited[id])
+                .min_by(|&x, &y| {
+                    self.arc(current_index, x)
+                        .partial_cmp(&self.arc(current_index, y))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .unwrap();
+            order.push(min_index);
             // Mark the node as visited
-            visited.insert(min_index, true);
+            visited[min_index] = true;
             current_index = min_index;
         }
-        route.push(self.nodes[0].clone());
-        self.route = route;
+        self.set_order(order);
     }
 
-    pub fn two_opt(&mut self) {
+    /// Cheapest insertion: grows a cycle from the depot, each step adding the stop that
+    /// raises its cost the least, at the position where it does so.
+    pub fn cheapest_insertion_tour(&mut self) {
         self.distance_matrix();
-        let mut improved = true;
-        while improved {
-            improved = false;
-            for i in 1..self.route.len() - 2 {
-                for k in i + 1..self.route.len() - 1 {
-                    let old_distance = self.distance[self.route[i - 1].id][self.route[i].id].unwrap_or(0.0)
-                        + self.distance[self.route[k].id][self.route[k + 1].id].unwrap_or(0.0);
-                    let new_distance = self.distance[self.route[i - 1].id][self.route[k].id].unwrap_or(0.0)
-                        + self.distance[self.route[i].id][self.route[k + 1].id].unwrap_or(0.0);
-                    let delta = old_distance - new_distance;
-                    if delta > 0.0 {
-                        self.route[i..=k].reverse();
-                        improved = true;
-                        break;
+        self.insertion_from(vec![self.depot], false);
+    }
+
+    /// Farthest insertion: each step adds the stop farthest from the cycle so far, at its
+    /// cheapest position. Sketching the outline first avoids long closing edges.
+    pub fn farthest_insertion_tour(&mut self) {
+        self.distance_matrix();
+        self.insertion_from(vec![self.depot], true);
+    }
+
+    /// Convex hull insertion: starts from the convex hull of the stop positions and adds
+    /// the remaining stops by cheapest insertion. The virtual depot of a route with two
+    /// free ends has no position and is inserted like any other stop.
+    pub fn convex_hull_tour(&mut self) {
+        self.distance_matrix();
+        let points: Vec<usize> = self.order().into_iter().filter(|&id| id < self.nodes.len()).collect();
+        let hull = convex_hull(&points, &self.nodes);
+        self.insertion_from(hull, false);
+    }
+
+    /// Insertion heuristics on top of `cycle`. Tracks each outside stop's cheapest slot,
+    /// refreshing it only when that slot's edge is split, and picks the next stop by
+    /// cheapest slot or, with `farthest`, by largest distance to the cycle.
+    fn insertion_from(&mut self, mut cycle: Vec<usize>, farthest: bool) {
+        let ids = self.order();
+        let size = ids.iter().max().map_or(0, |&m| m + 1);
+        let mut inside = vec![false; size];
+        for &id in &cycle {
+            inside[id] = true;
+        }
+        let mut left: Vec<usize> = ids.iter().copied().filter(|&id| !inside[id]).collect();
+        let arc = |a: usize, b: usize| self.arc(a, b) as f64;
+        let slot_cost = |cycle: &[usize], i: usize, k: usize| {
+            let (a, b) = (cycle[i], cycle[(i + 1) % cycle.len()]);
+            arc(a, k) + arc(k, b) - arc(a, b)
+        };
+        // Cheapest slot per outside stop: (cost, node the stop goes after)
+        let cheapest = |cycle: &[usize], k: usize| {
+            (0..cycle.len())
+                .map(|i| (slot_cost(cycle, i, k), cycle[i]))
+                .min_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(std::cmp::Ordering::Equal))
+                .unwrap_or((0.0, cycle[0]))
+        };
+        let mut slot = vec![(0.0, 0); size];
+        let mut reach = vec![f64::INFINITY; size];
+        for &k in &left {
+            slot[k] = cheapest(&cycle, k);
+            reach[k] = cycle.iter().map(|&c| arc(c, k).min(arc(k, c))).fold(f64::INFINITY, f64::min);
+        }
+
+        while !left.is_empty() {
+            let key = |k: usize| if farthest { -reach[k] } else { slot[k].0 };
+            let (i, &k) = left.iter().enumerate()
+                .min_by(|(_, &x), (_, &y)| key(x).partial_cmp(&key(y)).unwrap_or(std::cmp::Ordering::Equal))
+                .expect("left is not empty");
+            left.swap_remove(i);
+            let after = slot[k].1;
+            let p = cycle.iter().position(|&c| c == after).expect("slot is on the cycle");
+            let before = cycle[(p + 1) % cycle.len()];
+            cycle.insert(p + 1, k);
+            for &o in &left {
+                reach[o] = reach[o].min(arc(k, o)).min(arc(o, k));
+                if slot[o].1 == after {
+                    // The edge after → before this slot used is gone
+                    slot[o] = cheapest(&cycle, o);
+                } else {
+                    for (a, b) in [(after, k), (k, before)] {
+                        let c = arc(a, o) + arc(o, b) - arc(a, b);
+                        if c < slot[o].0 {
+                            slot[o] = (c, a);
+                        }
                     }
                 }
-                if improved {
-                    break;
-                }
             }
         }
+        self.set_order_oriented(cycle);
     }
 
-    // Or-opt: moves segments of size 1, 2, and 3 to a better position in the tour.
-    // Runs after 2-opt to escape local optima that 2-opt cannot improve.
-    pub fn or_opt(&mut self) {
+    /// Greedy edge matching: takes the cheapest edges between candidate neighbours that
+    /// keep every stop at degree two or less without closing a cycle early, then chains
+    /// the resulting paths end to nearest end.
+    pub fn greedy_edge_tour(&mut self) {
         self.distance_matrix();
-        let mut improved = true;
-        while improved {
-            improved = false;
-            // n = number of cities (route has n+1 nodes, last == first)
-            let n = self.route.len() - 1;
-            'outer: for seg_size in 1..=3 {
-                for i in 1..n - seg_size + 1 {
-                    // Segment is route[i..i+seg_size]
-                    let prev  = i - 1;
-                    let last  = i + seg_size - 1;
-                    let next  = i + seg_size; // may equal n (the closing node)
-
-                    if next > n { continue; }
-
-                    // Cost of removing the segment from its current position
-                    let removal_gain =
-                        self.distance[self.route[prev].id][self.route[i].id].unwrap_or(0.0)
-                        + self.distance[self.route[last].id][self.route[next].id].unwrap_or(0.0)
-                        - self.distance[self.route[prev].id][self.route[next].id].unwrap_or(0.0);
-
-                    // Try inserting the segment after every other position j
-                    for j in 1..n {
-                        // Skip positions that overlap with the segment itself
-                        if j >= prev && j <= last { continue; }
-
-                        let j_next = if j + 1 > n { 1 } else { j + 1 };
-
-                        let insertion_cost =
-                            self.distance[self.route[j].id][self.route[i].id].unwrap_or(0.0)
-                            + self.distance[self.route[last].id][self.route[j_next].id].unwrap_or(0.0)
-                            - self.distance[self.route[j].id][self.route[j_next].id].unwrap_or(0.0);
-
-                        if removal_gain - insertion_cost > 1e-6 {
-                            // Rebuild route with segment relocated
-                            let segment: Vec<Node> = self.route[i..=last].to_vec();
-                            let mut new_route: Vec<Node> = Vec::with_capacity(self.route.len());
-
-                            // Walk the original route skipping the segment,
-                            // inserting it after position j
-                            let mut k = 0;
-                            while k <= n {
-                                if k == i {
-                                    k = last + 1; // skip segment
-                                    continue;
-                                }
-                                new_route.push(self.route[k].clone());
-                                // Insert segment after the adjusted j position
-                                let adjusted_j = if j < i { j } else { j - seg_size };
-                                if new_route.len() - 1 == adjusted_j {
-                                    new_route.extend(segment.iter().cloned());
-                                }
-                                k += 1;
-                            }
-
-                            debug_assert_eq!(new_route.len(), self.route.len(), "or_opt rebuild produced wrong length");
-                            self.route = new_route;
-                            improved = true;
-                            break 'outer;
-                        }
+        let ids = self.order();
+        let m = ids.len();
+        if m < 3 {
+            return;
+        }
+        let size = ids.iter().max().map_or(0, |&x| x + 1);
+        let cands = self.candidate_lists(&ids, NEIGHBOUR_CANDIDATES);
+        let mut edges: Vec<(f64, usize, usize)> = ids.iter()
+            .flat_map(|&a| cands[a].iter().map(move |&b| (a.min(b), a.max(b))))
+            .map(|(a, b)| (self.average_arc(a, b), a, b))
+            .collect();
+        edges.sort_by(|x, y| x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Equal));
+        edges.dedup_by(|x, y| (x.1, x.2) == (y.1, y.2));
+
+        let mut adj: Vec<Vec<usize>> = vec![Vec::new(); size];
+        let mut group: Vec<usize> = (0..size).collect();
+        fn root(group: &mut [usize], mut x: usize) -> usize {
+            while group[x] != x {
+                group[x] = group[group[x]];
+                x = group[x];
+            }
+            x
+        }
+        let mut taken = 0;
+        for (_, a, b) in edges {
+            if taken == m - 1 {
+                break;
+            }
+            let (ra, rb) = (root(&mut group, a), root(&mut group, b));
+            if adj[a].len() < 2 && adj[b].len() < 2 && ra != rb {
+                adj[a].push(b);
+                adj[b].push(a);
+                group[ra] = rb;
+                taken += 1;
+            }
+        }
+
+        // Walk each path from one end, then jump to the nearest free end of another path
+        let mut visited = vec![false; size];
+        let mut cycle = Vec::with_capacity(m);
+        let mut current = ids.iter().copied().find(|&id| adj[id].len() < 2).unwrap_or(ids[0]);
+        loop {
+            let mut prev = usize::MAX;
+            loop {
+                visited[current] = true;
+                cycle.push(current);
+                match adj[current].iter().copied().find(|&x| x != prev && !visited[x]) {
+                    Some(next) => {
+                        prev = current;
+                        current = next;
                     }
+                    None => break,
                 }
             }
+            let tail = current;
+            match ids.iter().copied()
+                .filter(|&id| !visited[id] && adj[id].len() < 2)
+                .min_by(|&x, &y| {
+                    self.average_arc(tail, x).partial_cmp(&self.average_arc(tail, y))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                }) {
+                Some(next) => current = next,
+                None => break,
+            }
         }
+        self.set_order_oriented(cycle);
     }
 
-}
+    /// Orders stops along a Hilbert curve over their positions, so nearby stops are
+    /// visited together. O(n log n) and independent of the metric; the virtual depot of a
+    /// route with two free ends goes first.
+    pub fn space_filling_curve_tour(&mut self) {
+        let n = self.nodes.len();
+        let ids = self.order();
+        let (mut lo, mut hi) = ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN));
+        for node in &self.nodes {
+            lo = (lo.0.min(node.x), lo.1.min(node.y));
+            hi = (hi.0.max(node.x), hi.1.max(node.y));
+        }
+        let side = (hi.0 - lo.0).max(hi.1 - lo.1).max(f32::EPSILON);
+        let scale = |v: f32, min: f32| (((v - min) / side) * (HILBERT_SIDE - 1) as f32) as u32;
+        let mut keyed: Vec<(u64, usize)> = ids.iter()
+            .map(|&id| {
+                let key = if id < n {
+                    hilbert_index(scale(self.nodes[id].x, lo.0), scale(self.nodes[id].y, lo.1))
+                } else {
+                    0
+                };
+                (key, id)
+            })
+            .collect();
+        keyed.sort_unstable();
+        self.set_order(keyed.into_iter().map(|(_, id)| id).collect());
+    }
 
-pub fn haversine_km(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
-    const R: f64 = 6371.0;
-    let dlat = (lat2 - lat1).to_radians();
-    let dlng = (lng2 - lng1).to_radians();
-    let a = (dlat / 2.0).sin().powi(2)
-        + lat1.to_radians().cos() * lat2.to_radians().cos()
-        * (dlng / 2.0).sin().powi(2);
-    R * 2.0 * a.sqrt().asin()
-}
+    /// Christofides: minimum spanning tree, plus a minimum-weight matching of its
+    /// odd-degree stops (exact for up to `EXACT_MATCHING_MAX` of them, greedy beyond),
+    /// walked as an Euler circuit with repeated stops skipped. Within 1.5× the optimum on
+    /// symmetric metric costs; asymmetric costs use the average of both directions.
+    pub fn christofides_tour(&mut self) {
+        self.distance_matrix();
+        let ids = self.order();
+        let m = ids.len();
+        if m < 3 {
+            return;
+        }
+        let w = |a: usize, b: usize| self.average_arc(ids[a], ids[b]);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // Prim on local indices
+        let mut in_tree = vec![false; m];
+        let mut reach = vec![f64::INFINITY; m];
+        let mut parent = vec![0; m];
+        let mut edges: Vec<(usize, usize)> = Vec::with_capacity(2 * m);
+        reach[0] = 0.0;
+        for _ in 0..m {
+            let u = (0..m)
+                .filter(|&v| !in_tree[v])
+                .min_by(|&a, &b| reach[a].partial_cmp(&reach[b]).unwrap_or(std::cmp::Ordering::Equal))
+                .expect("a node is left");
+            in_tree[u] = true;
+            if u != 0 {
+                edges.push((parent[u], u));
+            }
+            for v in 0..m {
+                if !in_tree[v] && w(u, v) < reach[v] {
+                    reach[v] = w(u, v);
+                    parent[v] = u;
+                }
+            }
+        }
 
-    // ── Haversine Distance Tests ───────────────────────────────────────────────
+        let mut degree = vec![0; m];
+        for &(a, b) in &edges {
+            degree[a] += 1;
+            degree[b] += 1;
+        }
+        let odd: Vec<usize> = (0..m).filter(|&v| degree[v] % 2 == 1).collect();
+        edges.extend(min_weight_matching(&odd, &w));
 
-    #[test]
-    fn haversine_london_to_paris() {
-        // London (51.5074, -0.1278) → Paris (48.8566, 2.3522) ≈ 341 km
-        let d = haversine_km(51.5074, -0.1278, 48.8566, 2.3522);
-        assert!((d - 341.0).abs() < 5.0, "expected ~341 km, got {:.1}", d);
+        // Hierholzer's algorithm over the multigraph, then shortcut repeated stops
+        let mut adj: Vec<Vec<(usize, usize)>> = vec![Vec::new(); m];
+        for (e, &(a, b)) in edges.iter().enumerate() {
+            adj[a].push((b, e));
+            adj[b].push((a, e));
+        }
+        let mut used = vec![false; edges.len()];
+        let mut stack = vec![0];
+        let mut circuit = Vec::with_capacity(edges.len() + 1);
+        while let Some(&v) = stack.last() {
+            while adj[v].last().is_some_and(|&(_, e)| used[e]) {
+                adj[v].pop();
+            }
+            match adj[v].pop() {
+                Some((u, e)) => {
+                    used[e] = true;
+                    stack.push(u);
+                }
+                None => {
+                    circuit.push(v);
+                    stack.pop();
+                }
+            }
+        }
+        let mut seen = vec![false; m];
+        let cycle: Vec<usize> = circuit.into_iter()
+            .filter(|&v| !std::mem::replace(&mut seen[v], true))
+            .map(|v| ids[v])
+            .collect();
+        self.set_order_oriented(cycle);
     }
 
-    #[test]
-    fn haversine_zero_distance() {
+    /// Mean of both directions of an arc: the symmetric cost greedy edge and Christofides
+    /// work on.
+    fn average_arc(&self, a: usize, b: usize) -> f64 {
+        (self.arc(a, b) as f64 + self.arc(b, a) as f64) / 2.0
+    }
+
+    /// `set_order` with whichever direction around `cycle` is cheaper.
+    fn set_order_oriented(&mut self, cycle: Vec<usize>) {
+        let reversed: Vec<usize> = cycle.iter().rev().copied().collect();
+        if self.order_cost(&reversed) < self.order_cost(&cycle) - EPS {
+            self.set_order(reversed);
+        } else {
+            self.set_order(cycle);
+        }
+    }
+
+    /// 2-opt: reverses the segment between two edges whenever that shortens the tour.
+    /// Reversing a segment also flips the direction of every edge inside it, so the delta
+    /// includes that change — on asymmetric matrices (e.g. OSRM road times) those internal
+    /// edges do not cost the same both ways.
+    ///
+    /// Only each node's `NEIGHBOUR_CANDIDATES` nearest neighbours are tried as the new
+    /// edge, and don't-look bits skip nodes whose surroundings have not changed since they
+    /// last failed to improve, so this scales to thousands of stops.
+    pub fn two_opt(&mut self) {
+        self.distance_matrix();
+        let order = self.order();
+        if order.len() < 3 {
+            return;
+        }
+        let cands = self.candidate_lists(&order, NEIGHBOUR_CANDIDATES);
+        self.two_opt_with(&cands, self.is_symmetric(&order));
+    }
+
+    /// `two_opt` with candidate lists and symmetry already worked out for the route's
+    /// stops, so repeated runs over the same stops skip the O(n²) setup.
+    fn two_opt_with(&mut self, cands: &[Vec<usize>], symmetric: bool) {
+        // Order constraints need the cycle to keep its direction, which symmetric flips may not
+        let symmetric = symmetric && self.constraints.is_empty();
+        let mut lk = LkState::new(self.order(), symmetric);
+        let mut active = DontLook::new(&lk.order);
+        while let Some(a) = active.next() {
+            if self.budget.exhausted() {
+                break;
+            }
+            if let Some(touched) = self.two_opt_at(&mut lk, &cands[a], a) {
+                active.wake(&touched);
+            }
+        }
+        self.set_order(lk.order);
+    }
+
+    /// Tries the 2-opt moves that give `a` a new edge to one of its candidates, in both
+    /// directions around the cycle. Applies the first improving one that keeps the order
+    /// constraints and returns the endpoints of the edges it changed.
+    fn two_opt_at(&self, lk: &mut LkState, cands: &[usize], a: usize) -> Option<[usize; 4]> {
+        let arc = |x: usize, y: usize| self.arc(x, y) as f64;
+        for forward in [true, false] {
+            // Forward: a→b … c→d becomes a→c … b→d, reversing b..c.
+            // Backward: d→c … b→a becomes d→b … c→a, reversing c..b.
+            let b = if forward { lk.succ(a) } else { lk.pred(a) };
+            let removed = if forward { arc(a, b) } else { arc(b, a) };
+            for &c in cands {
+                let added = if forward { arc(a, c) } else { arc(c, a) };
+                if added >= removed - EPS {
+                    // Lists are sorted by arc(a, c), so later candidates are no better
+                    if forward || lk.symmetric {
+                        break;
+                    }
+                    continue;
+                }
+                let d = if forward { lk.succ(c) } else { lk.pred(c) };
+                if c == b || d == a {
+                    continue;
+                }
+                let (from, to) = if forward { (b, c) } else { (c, b) };
+                let mut delta = if forward {
+                    added + arc(b, d) - removed - arc(c, d)
+                } else {
+                    arc(d, b) + added - arc(d, c) - removed
+                };
+                if !lk.symmetric {
+                    let mut x = from;
+                    while x != to {
+                        let y = lk.succ(x);
+                        delta += arc(y, x) - arc(x, y);
+                        x = y;
+                    }
+                }
+                if delta < -EPS {
+                    lk.flip_shorter(from, to);
+                    if self.keeps_order(lk) {
+                        return Some([a, b, c, d]);
+                    }
+                    // Only reached with order constraints, where flip_shorter is a plain flip
+                    lk.flip(to, from);
+                }
+            }
+        }
+        None
+    }
+
+    /// Or-opt: moves segments of one to three stops next to one of their candidate
+    /// neighbours, as they are or reversed. A reversed segment's delta includes its
+    /// internal edges changing direction, so it is exact for asymmetric matrices too.
+    /// Runs after 2-opt to escape local optima 2-opt cannot improve, with the same
+    /// candidate lists and don't-look bits.
+    pub fn or_opt(&mut self) {
+        self.distance_matrix();
+        let order = self.order();
+        if order.len() < 4 {
+            return;
+        }
+        let cands = self.candidate_lists(&order, NEIGHBOUR_CANDIDATES);
+        self.or_opt_with(&cands, self.is_symmetric(&order));
+    }
+
+    /// `or_opt` with candidate lists and symmetry already worked out, as in `two_opt_with`.
+    fn or_opt_with(&mut self, cands: &[Vec<usize>], symmetric: bool) {
+        let mut lk = LkState::new(self.order(), symmetric);
+        let mut active = DontLook::new(&lk.order);
+        while let Some(a) = active.next() {
+            if self.budget.exhausted() {
+                break;
+            }
+            if let Some(touched) = self.or_opt_at(&mut lk, cands, a) {
+                active.wake(&touched);
+            }
+        }
+        self.set_order(lk.order);
+    }
+
+    /// Tries moving the segments that start at `a`, either way round, between a candidate
+    /// neighbour of the segment's ends and that neighbour's successor or predecessor.
+    /// Applies the first improving move that keeps the order constraints and returns the
+    /// nodes whose edges changed.
+    fn or_opt_at(&self, lk: &mut LkState, cands: &[Vec<usize>], a: usize) -> Option<[usize; 6]> {
+        let arc = |x: usize, y: usize| self.arc(x, y) as f64;
+        let m = lk.order.len();
+        let mut segment = vec![a];
+        for len in 1..=3.min(m - 2) {
+            if len > 1 {
+                segment.push(lk.succ(segment[len - 2]));
+            }
+            let (first, last) = (a, segment[len - 1]);
+            let (prev, next) = (lk.pred(first), lk.succ(last));
+            let removal_gain = arc(prev, first) + arc(last, next) - arc(prev, next);
+            if removal_gain <= EPS {
+                continue;
+            }
+            let reversal: f64 = segment.windows(2).map(|w| arc(w[1], w[0]) - arc(w[0], w[1])).sum();
+            for &c in cands[first].iter().chain(&cands[last]) {
+                if segment.contains(&c) {
+                    continue;
+                }
+                // Insert between (x, y), an edge that survives removing the segment
+                for (x, y) in [(c, lk.succ(c)), (lk.pred(c), c)] {
+                    if segment.contains(&x) || segment.contains(&y) {
+                        continue;
+                    }
+                    let as_is = arc(x, first) + arc(last, y) - arc(x, y);
+                    let reversed = arc(x, last) + arc(first, y) - arc(x, y) + reversal;
+                    if removal_gain - as_is.min(reversed) > EPS {
+                        if reversed < as_is {
+                            let flipped: Vec<usize> = segment.iter().rev().copied().collect();
+                            lk.relocate(&flipped, x);
+                        } else {
+                            lk.relocate(&segment, x);
+                        }
+                        if self.keeps_order(lk) {
+                            return Some([prev, first, last, next, x, y]);
+                        }
+                        lk.relocate(&segment, prev);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// 3-opt: removes three edges and tries every way of reconnecting the two segments
+    /// between them, reversed or not, including swapping them ("or-3opt", segment
+    /// insertion of any length). Triples come from candidate neighbours of the first
+    /// removed edge's ends, with don't-look bits as in 2-opt; reversed segments add the
+    /// direction change of their internal edges, so deltas are exact on any matrix.
+    pub fn three_opt(&mut self) {
+        self.distance_matrix();
+        let order = self.order();
+        if order.len() < 5 {
+            return;
+        }
+        let symmetric = self.is_symmetric(&order);
+        let cands = self.candidate_lists(&order, NEIGHBOUR_CANDIDATES);
+        let mut lk = LkState::new(order, symmetric);
+        let mut active = DontLook::new(&lk.order);
+        while let Some(a) = active.next() {
+            if self.budget.exhausted() {
+                break;
+            }
+            if let Some(touched) = self.three_opt_at(&mut lk, &cands, a) {
+                active.wake(&touched);
+            }
+        }
+        self.set_order(lk.order);
+    }
+
+    /// Looks for an improving 3-opt move that removes the edge a→b (b = succ(a)) and
+    /// adds an edge from `a` to a candidate neighbour. Applies the cheapest improving
+    /// reconnection that keeps the order constraints, for the first triple that has one,
+    /// and returns the six nodes around the removed edges.
+    fn three_opt_at(&self, lk: &mut LkState, cands: &[Vec<usize>], a: usize) -> Option<[usize; 6]> {
+        let m = lk.order.len();
+        let b = lk.succ(a);
+        let g = self.arc(a, b) as f64;
+        // Offset from `a` along the cycle; the removed edges are a→b, c→d and e→f with
+        // rel(b) <= rel(c) < rel(d) <= rel(e)
+        let rel = |lk: &LkState, x: usize| (lk.pos[x] + m - lk.pos[a]) % m;
+        let mut triples: Vec<(usize, usize)> = Vec::new();
+        for &p in &cands[a] {
+            if self.arc(a, p) as f64 >= g - EPS {
+                break;
+            }
+            if p == b {
+                continue;
+            }
+            // p as d (a→d): e is a neighbour of b or of c = pred(d)
+            let c = lk.pred(p);
+            for &e in cands[b].iter().chain(&cands[c]) {
+                if e != a && rel(lk, e) >= rel(lk, p) {
+                    triples.push((c, e));
+                }
+            }
+            // p as e (a→e): d is a neighbour of b, between b and e
+            for &d in &cands[b] {
+                if rel(lk, d) > 1 && rel(lk, d) <= rel(lk, p) {
+                    triples.push((lk.pred(d), p));
+                }
+            }
+            // p as c (a→c): e is a neighbour of b, after d = succ(c)
+            let d = lk.succ(p);
+            if d != a {
+                for &e in &cands[b] {
+                    if e != a && rel(lk, e) >= rel(lk, d) {
+                        triples.push((p, e));
+                    }
+                }
+            }
+        }
+        for (c, e) in triples {
+            let ends = [a, b, c, lk.succ(c), e, lk.succ(e)];
+            for reconnection in self.improving_reconnections(lk, ends) {
+                let saved = (!self.constraints.is_empty()).then(|| (lk.order.clone(), lk.pos.clone()));
+                lk.reconnect(ends, reconnection);
+                match saved {
+                    Some((order, pos)) if !self.keeps_order(lk) => (lk.order, lk.pos) = (order, pos),
+                    _ => return Some(ends),
+                }
+            }
+        }
+        None
+    }
+
+    /// Which of the seven ways to reconnect a [b..c] [d..e] f improve on the current
+    /// edges a→b, c→d, e→f, cheapest first. See `LkState::reconnect` for the numbering.
+    fn improving_reconnections(&self, lk: &LkState, [a, b, c, d, e, f]: [usize; 6]) -> Vec<usize> {
+        let arc = |x: usize, y: usize| self.arc(x, y) as f64;
+        let reversal = |from: usize, to: usize| {
+            if lk.symmetric {
+                return 0.0;
+            }
+            let (mut x, mut sum) = (from, 0.0);
+            while x != to {
+                let y = lk.succ(x);
+                sum += arc(y, x) - arc(x, y);
+                x = y;
+            }
+            sum
+        };
+        let (rev1, rev2) = (reversal(b, c), reversal(d, e));
+        let removed = arc(a, b) + arc(c, d) + arc(e, f);
+        let options = [
+            arc(a, c) + arc(b, d) + arc(e, f) + rev1,
+            arc(a, b) + arc(c, e) + arc(d, f) + rev2,
+            arc(a, e) + arc(d, c) + arc(b, f) + rev1 + rev2,
+            arc(a, d) + arc(e, b) + arc(c, f),
+            arc(a, d) + arc(e, c) + arc(b, f) + rev1,
+            arc(a, e) + arc(d, b) + arc(c, f) + rev2,
+            arc(a, c) + arc(b, e) + arc(d, f) + rev1 + rev2,
+        ];
+        let mut improving: Vec<usize> = (0..options.len()).filter(|&i| options[i] < removed - EPS).collect();
+        improving.sort_by(|&x, &y| options[x].partial_cmp(&options[y]).unwrap_or(std::cmp::Ordering::Equal));
+        improving
+    }
+
+    /// Arrival, waiting, departure and lateness for every stop of the current route.
+    /// Vehicles that arrive early wait for the window to open; the return to the first
+    /// stop of a closed loop only checks that stop's `latest`.
+    pub fn schedule(&self) -> Vec<StopTime> {
+        let ids: Vec<usize> = self.route.iter().map(|n| n.id).collect();
+        self.simulate(&ids)
+    }
+
+    /// Whether every stop of the current route is reached within its window.
+    pub fn meets_time_windows(&self) -> bool {
+        self.schedule().iter().all(|s| s.late <= 0.0)
+    }
+
+    fn simulate(&self, path: &[usize]) -> Vec<StopTime> {
+        let closed = self.is_closed();
+        let mut times = Vec::with_capacity(path.len());
+        let mut clock = self.departure;
+        for (i, &id) in path.iter().enumerate() {
+            if i > 0 {
+                clock += self.distance(&self.nodes[path[i - 1]], &self.nodes[id]);
+            }
+            let arrival = clock;
+            let window = self.windows.get(id).copied().flatten();
+            let returning = closed && i + 1 == path.len() && i > 0;
+            let (wait, late) = match window {
+                Some(w) => (
+                    if returning { 0.0 } else { (w.earliest - arrival).max(0.0) },
+                    (arrival - w.latest).max(0.0),
+                ),
+                None => (0.0, 0.0),
+            };
+            let service = if returning { 0.0 } else { self.service[id] };
+            clock = arrival + wait + service;
+            times.push(StopTime { id, arrival, wait, departure: clock, late });
+        }
+        times
+    }
+
+    /// Total lateness and finishing time of the route described by `order` (see `order`).
+    fn window_score(&self, order: &[usize]) -> (f64, f64) {
+        let times = self.simulate(&self.path_ids(order));
+        let late = times.iter().map(|t| t.late as f64).sum();
+        let finish = times.last().map_or(0.0, |t| t.departure as f64);
+        (late, finish)
+    }
+
+    /// Time-window search: reorders the route to minimise total lateness first and the
+    /// finishing time second, relocating segments of 1–3 stops and reversing segments.
+    /// Every move is evaluated by simulating the whole schedule, which is fine for the
+    /// route sizes OSRM can serve.
+    pub fn time_window_search(&mut self) {
+        self.distance_matrix();
+        let mut best = self.order();
+        let mut best_score = self.window_score(&best);
+
+        // Earliest deadline first is a strong start when windows are tight
+        let mut edf = best.clone();
+        let deadline = |id: usize| self.windows.get(id).copied().flatten().map_or(f32::MAX, |w| w.latest);
+        edf[1..].sort_by(|&a, &b| deadline(a).partial_cmp(&deadline(b)).unwrap_or(std::cmp::Ordering::Equal));
+        let edf_score = self.window_score(&edf);
+        if beats(edf_score, best_score) && self.respects_order(&edf) {
+            best = edf;
+            best_score = edf_score;
+        }
+
+        let m = best.len();
+        let mut improved = true;
+        while improved && !self.budget.exhausted() {
+            improved = false;
+            'moves: for seg_size in 1..=3 {
+                for i in 1..(m + 1).saturating_sub(seg_size) {
+                    if self.budget.exhausted() {
+                        break 'moves;
+                    }
+                    let mut rest = best.clone();
+                    let segment: Vec<usize> = rest.drain(i..i + seg_size).collect();
+                    for p in 1..=rest.len() {
+                        if p == i {
+                            continue;
+                        }
+                        let mut candidate = rest.clone();
+                        candidate.splice(p..p, segment.iter().copied());
+                        let score = self.window_score(&candidate);
+                        if beats(score, best_score) && self.respects_order(&candidate) {
+                            best = candidate;
+                            best_score = score;
+                            improved = true;
+                            break 'moves;
+                        }
+                    }
+                }
+            }
+            if improved {
+                continue;
+            }
+            'reversals: for i in 1..m.saturating_sub(1) {
+                for k in i + 1..m {
+                    let mut candidate = best.clone();
+                    candidate[i..=k].reverse();
+                    let score = self.window_score(&candidate);
+                    if beats(score, best_score) && self.respects_order(&candidate) {
+                        best = candidate;
+                        best_score = score;
+                        improved = true;
+                        break 'reversals;
+                    }
+                }
+            }
+        }
+        self.set_order(best);
+    }
+
+    /// Builds a route with `strategy`'s construction, runs its improvement phases and
+    /// updates `cost`.
+    pub fn solve(&mut self, strategy: &Strategy) {
+        self.construct(strategy.construction);
+        self.run_improvements(strategy);
+    }
+
+    /// Runs `strategy` from `starts` different initial routes on up to `threads` worker
+    /// threads and keeps the cheapest result. The first start is the strategy's own
+    /// construction, the next ones nearest neighbour from other nodes, and random routes
+    /// once every node has been tried. Workers share the distance matrix and get their
+    /// seeds from this tour's RNG, so a given seed gives the same route on any machine.
+    pub fn solve_multi_start(&mut self, strategy: &Strategy, starts: usize, threads: usize) {
+        self.distance_matrix();
+        let ids = self.order();
+        let seeds: Vec<u64> = (0..starts.max(1)).map(|_| self.rng.gen()).collect();
+        let next = AtomicUsize::new(0);
+        let base = &*self;
+        let run = |k: usize| {
+            let mut tour = base.clone();
+            tour.set_seed(seeds[k]);
+            match k {
+                0 => tour.construct(strategy.construction),
+                k if k < ids.len() => tour.nearest_neighbour_from(ids[k]),
+                _ => tour.random_tour(),
+            }
+            tour.run_improvements(strategy);
+            tour
+        };
+        let best = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads.clamp(1, seeds.len()))
+                .map(|_| scope.spawn(|| {
+                    let mut best: Option<(usize, Tour)> = None;
+                    loop {
+                        let k = next.fetch_add(1, Ordering::Relaxed);
+                        // Start 0 always runs, so there is a result even on an exhausted budget
+                        if k >= seeds.len() || (k > 0 && base.budget.exhausted()) {
+                            break best;
+                        }
+                        let tour = run(k);
+                        if best.as_ref().is_none_or(|(_, b)| tour.cost < b.cost) {
+                            best = Some((k, tour));
+                        }
+                    }
+                }))
+                .collect();
+            workers.into_iter()
+                .filter_map(|w| w.join().expect("multi-start worker panicked"))
+                // Ties go to the lowest start so the winner does not depend on scheduling
+                .min_by(|(ka, a), (kb, b)| a.cost.partial_cmp(&b.cost).unwrap_or(std::cmp::Ordering::Equal).then(ka.cmp(kb)))
+        });
+        if let Some((_, tour)) = best {
+            self.route = tour.route;
+            self.cost = tour.cost;
+        }
+    }
+
+    /// The improvement phases of `strategy`, repeated while `until_stable` pays off.
+    fn run_improvements(&mut self, strategy: &Strategy) {
+        self.repair_order();
+        self.calculate_cost();
+        loop {
+            let cost_before = self.cost;
+            for improvement in strategy.improvements.iter() {
+                self.improve(improvement);
+            }
+            self.calculate_cost();
+            if !strategy.until_stable || self.cost >= cost_before - EPS as f32 || self.budget.exhausted() {
+                break;
+            }
+        }
+    }
+
+    pub fn construct(&mut self, construction: Construction) {
+        match construction {
+            Construction::NearestNeighbour => self.nearest_neighbour_tour(),
+            Construction::Random => self.random_tour(),
+            Construction::CheapestInsertion => self.cheapest_insertion_tour(),
+            Construction::FarthestInsertion => self.farthest_insertion_tour(),
+            Construction::ConvexHullInsertion => self.convex_hull_tour(),
+            Construction::GreedyEdge => self.greedy_edge_tour(),
+            Construction::SpaceFillingCurve => self.space_filling_curve_tour(),
+            Construction::Christofides => self.christofides_tour(),
+        }
+    }
+
+    pub fn improve(&mut self, improvement: &Improvement) {
+        // The local searches check the order constraints move by move; the others get rolled back
+        let checks_moves = matches!(improvement, Improvement::TwoOpt | Improvement::OrOpt
+            | Improvement::LinKernighan | Improvement::ThreeOpt | Improvement::IteratedLocalSearch { .. });
+        let before = (!self.constraints.is_empty() && !checks_moves).then(|| self.order());
+        match improvement {
+            Improvement::TwoOpt => self.two_opt(),
+            Improvement::OrOpt => self.or_opt(),
+            Improvement::LinKernighan => self.lin_kernighan(),
+            Improvement::ThreeOpt => self.three_opt(),
+            Improvement::Annealing(schedule) => self.simulated_annealing(schedule),
+            Improvement::IteratedLocalSearch { kicks } => self.iterated_local_search(*kicks),
+            Improvement::Genetic(settings) => genetic::evolve(self, settings),
+            Improvement::AntColony(colony) => self.ant_colony(colony),
+        }
+        if let Some(before) = before {
+            if !self.respects_order(&self.order()) {
+                self.set_order(before);
+            }
+        }
+    }
+
+    /// Simulated annealing over random segment reversals and short segment relocations.
+    /// Uphill moves are accepted with probability exp(-delta / T) as T cools geometrically. The best route seen is kept,
+    /// so the result is never worse than the route it started from.
+    pub fn simulated_annealing(&mut self, schedule: &Annealing) {
+        self.distance_matrix();
+        let mut order = self.order();
+        let m = order.len();
+        if m < 4 || schedule.iterations == 0 {
+            return;
+        }
+        let symmetric = self.is_symmetric(&order);
+        let start_cost = self.order_cost(&order);
+        let (mut current, mut best) = (start_cost, start_cost);
+        let mut best_order = order.clone();
+        let mut temperature = schedule.initial_temperature.max(0.0) * start_cost / m as f64;
+        let cooling = if schedule.initial_temperature > 0.0 && schedule.final_temperature > 0.0 {
+            (schedule.final_temperature / schedule.initial_temperature).powf(1.0 / schedule.iterations as f64)
+        } else {
+            0.0
+        };
+        for step in 0..schedule.iterations {
+            if step.is_multiple_of(1024) && self.budget.exhausted() {
+                break;
+            }
+            temperature *= cooling;
+            let i = self.rng.gen_range(1..m);
+            let delta = if self.rng.gen_bool(0.5) {
+                // Reverse order[i..=j]
+                let j = self.rng.gen_range(1..m);
+                if i == j {
+                    continue;
+                }
+                let (i, j) = (i.min(j), i.max(j));
+                let arc = |a: usize, b: usize| self.arc(a, b) as f64;
+                let (a, b, c, d) = (order[i - 1], order[i], order[j], order[(j + 1) % m]);
+                let mut change = arc(a, c) + arc(b, d) - arc(a, b) - arc(c, d);
+                if !symmetric {
+                    change += (i..j).map(|k| arc(order[k + 1], order[k]) - arc(order[k], order[k + 1])).sum::<f64>();
+                }
+                if !self.accept(change, temperature) {
+                    continue;
+                }
+                order[i..=j].reverse();
+                change
+            } else {
+                // Move order[i..i + len] between order[q] and its successor, keeping its
+                // direction, which suits one-way-heavy matrices better than reversals
+                let len = self.rng.gen_range(1..=3).min(m - i);
+                let p = self.rng.gen_range(0..m - len);
+                let q = if p < i { p } else { p + len };
+                if q == i - 1 {
+                    continue;
+                }
+                let arc = |a: usize, b: usize| self.arc(a, b) as f64;
+                let (prev, first, last, next) = (order[i - 1], order[i], order[i + len - 1], order[(i + len) % m]);
+                let (after, before) = (order[q], order[(q + 1) % m]);
+                let change = arc(prev, next) - arc(prev, first) - arc(last, next)
+                    + arc(after, first) + arc(last, before) - arc(after, before);
+                if !self.accept(change, temperature) {
+                    continue;
+                }
+                let segment: Vec<usize> = order.drain(i..i + len).collect();
+                let at = if q < i { q + 1 } else { q + 1 - len };
+                order.splice(at..at, segment);
+                change
+            };
+            current += delta;
+            if current < best - EPS {
+                best = current;
+                best_order.clone_from(&order);
+            }
+        }
+        if self.order_cost(&best_order) < start_cost - EPS {
+            self.set_order(best_order);
+        }
+    }
+
+    /// Metropolis rule: always take improvements, and uphill moves with probability
+    /// exp(-delta / temperature).
+    fn accept(&mut self, delta: f64, temperature: f64) -> bool {
+        delta < 0.0 || self.rng.gen::<f64>() < (-delta / temperature).exp()
+    }
+
+    /// Iterated local search: `kicks` rounds of a random double-bridge move (a 4-opt
+    /// move 2-opt cannot undo) followed by 2-opt and Or-opt, moving on from each result
+    /// only when it beats the best route so far. Under order constraints each kicked
+    /// route is repaired before the local search. Every kick visits the same stops, so
+    /// the candidate lists are built once.
+    pub fn iterated_local_search(&mut self, kicks: usize) {
+        self.distance_matrix();
+        let order = self.order();
+        if order.len() < 8 {
+            return;
+        }
+        let cands = self.candidate_lists(&order, NEIGHBOUR_CANDIDATES);
+        let symmetric = self.is_symmetric(&order);
+        self.two_opt_with(&cands, symmetric);
+        self.or_opt_with(&cands, symmetric);
+        let mut best = self.order();
+        let mut best_cost = self.order_cost(&best);
+        for _ in 0..kicks {
+            if self.budget.exhausted() {
+                break;
+            }
+            let kicked = self.double_bridge(&best);
+            self.set_order(kicked);
+            self.repair_order();
+            self.two_opt_with(&cands, symmetric);
+            self.or_opt_with(&cands, symmetric);
+            let order = self.order();
+            let cost = self.order_cost(&order);
+            if cost < best_cost - EPS && self.respects_order(&order) {
+                best = order;
+                best_cost = cost;
+            }
+        }
+        self.set_order(best);
+    }
+
+    /// Ant colony system: each iteration every ant walks from the depot, at each step
+    /// taking the arc with the most pheromone × (1 / cost)² with probability
+    /// `ANT_EXPLOITATION` or sampling one in proportion to it otherwise, and wearing down
+    /// the pheromone on the arcs it takes so the colony keeps exploring. The iteration's
+    /// best route is improved with 2-opt, and the best route so far gets fresh pheromone.
+    /// Candidate lists are built once per colony. Keeps the best route found, so the result is never worse than the one it started from.
+    pub fn ant_colony(&mut self, colony: &AntColony) {
+        self.distance_matrix();
+        let start = self.order();
+        let m = start.len();
+        if m < 5 || colony.iterations == 0 || colony.ants == 0 {
+            return;
+        }
+        let symmetric = self.is_symmetric(&start);
+        let candidates = self.candidate_lists(&start, ANT_CANDIDATES);
+        // The 2-opt lists are the nearest of the ants' own, sorted nearest first
+        let local: Vec<Vec<usize>> = candidates.iter()
+            .map(|c| c[..c.len().min(NEIGHBOUR_CANDIDATES)].to_vec())
+            .collect();
+        let mut best_cost = self.order_cost(&start);
+        let mut best = start;
+        // Pheromone per arc, indexed [from][to] by node id
+        let size = candidates.len();
+        let initial = 1.0 / (m as f64 * best_cost.max(EPS));
+        let mut pheromone = vec![initial as f32; size * size];
+        // Keeps zero-cost arcs (duplicate stops, a free end) from dividing by zero
+        let floor = best_cost / m as f64 * 1e-3 + f64::MIN_POSITIVE;
+
+        for _ in 0..colony.iterations {
+            let mut iteration_best: Option<(Vec<usize>, f64)> = None;
+            for _ in 0..colony.ants {
+                if self.budget.exhausted() {
+                    break;
+                }
+                let walk = self.ant_walk(&best, &candidates, &mut pheromone, initial, floor, symmetric);
+                let cost = self.order_cost(&walk);
+                if iteration_best.as_ref().is_none_or(|(_, c)| cost < *c) {
+                    iteration_best = Some((walk, cost));
+                }
+            }
+            let Some((walk, _)) = iteration_best else { break };
+            self.set_order(walk);
+            self.two_opt_with(&local, symmetric);
+            let order = self.order();
+            let cost = self.order_cost(&order);
+            if cost < best_cost - EPS {
+                best = order;
+                best_cost = cost;
+            }
+
+            let deposit = 1.0 / best_cost.max(EPS);
+            for i in 0..m {
+                let (a, b) = (best[i], best[(i + 1) % m]);
+                let mut arcs = vec![a * size + b];
+                if symmetric {
+                    arcs.push(b * size + a);
+                }
+                for k in arcs {
+                    pheromone[k] = ((1.0 - ANT_EVAPORATION) * pheromone[k] as f64 + ANT_EVAPORATION * deposit) as f32;
+                }
+            }
+        }
+        self.set_order(best);
+    }
+
+    /// One ant's route over the stops of `order`, starting at the depot. Applies the
+    /// local pheromone update to each arc it takes.
+    fn ant_walk(
+        &mut self,
+        order: &[usize],
+        candidates: &[Vec<usize>],
+        pheromone: &mut [f32],
+        initial: f64,
+        floor: f64,
+        symmetric: bool,
+    ) -> Vec<usize> {
+        let size = candidates.len();
+        let m = order.len();
+        // Unvisited stops, with each one's index in `remaining` for O(1) removal
+        let mut remaining: Vec<usize> = order[1..].to_vec();
+        let mut slot = vec![usize::MAX; size];
+        for (i, &id) in remaining.iter().enumerate() {
+            slot[id] = i;
+        }
+        let mut walk = Vec::with_capacity(m);
+        walk.push(self.depot);
+        let mut weights: Vec<(usize, f64)> = Vec::with_capacity(ANT_CANDIDATES);
+        while !remaining.is_empty() {
+            let current = *walk.last().expect("walk starts at the depot");
+            let weight = |to: usize| {
+                let cost = self.arc(current, to) as f64 + floor;
+                pheromone[current * size + to] as f64 / (cost * cost)
+            };
+            weights.clear();
+            weights.extend(candidates[current].iter().filter(|&&id| slot[id] != usize::MAX).map(|&id| (id, weight(id))));
+            let next = if weights.is_empty() {
+                *remaining.iter()
+                    .max_by(|&&a, &&b| weight(a).total_cmp(&weight(b)))
+                    .expect("remaining is not empty")
+            } else if self.rng.gen_bool(ANT_EXPLOITATION) {
+                weights.iter().max_by(|a, b| a.1.total_cmp(&b.1)).expect("weights is not empty").0
+            } else {
+                let total: f64 = weights.iter().map(|w| w.1).sum();
+                let mut target = self.rng.gen::<f64>() * total;
+                weights.iter()
+                    .find(|w| {
+                        target -= w.1;
+                        target <= 0.0
+                    })
+                    .unwrap_or(weights.last().expect("weights is not empty"))
+                    .0
+            };
+
+            let mut arcs = vec![current * size + next];
+            if symmetric {
+                arcs.push(next * size + current);
+            }
+            for k in arcs {
+                pheromone[k] = ((1.0 - ANT_EVAPORATION) * pheromone[k] as f64 + ANT_EVAPORATION * initial) as f32;
+            }
+            let i = slot[next];
+            remaining.swap_remove(i);
+            if let Some(&moved) = remaining.get(i) {
+                slot[moved] = i;
+            }
+            slot[next] = usize::MAX;
+            walk.push(next);
+        }
+        walk
+    }
+
+    /// Cuts the cycle after the depot into segments A B C D and reconnects them A C B D.
+    pub(crate) fn double_bridge(&mut self, order: &[usize]) -> Vec<usize> {
+        let mut cuts = rand::seq::index::sample(&mut self.rng, order.len() - 1, 3).into_vec();
+        cuts.sort_unstable();
+        let (a, b, c) = (cuts[0] + 1, cuts[1] + 1, cuts[2] + 1);
+        [&order[..a], &order[b..c], &order[a..b], &order[c..]].concat()
+    }
+
+    /// Exact solver: Held-Karp dynamic programming up to `HELD_KARP_MAX_NODES` nodes and
+    /// branch and bound up to `BRANCH_AND_BOUND_MAX_NODES`. Returns `true` when the route
+    /// is now proven optimal. Larger instances are left alone, and a search cut short by
+    /// the budget or the expansion limit keeps the best route found and returns `false`.
+    /// Run a heuristic first: its route is the starting upper bound for branch and bound.
+    /// Routes with order constraints are left alone too.
+    pub fn solve_exact(&mut self) -> bool {
+        self.distance_matrix();
+        let m = self.order().len();
+        if !self.constraints.is_empty() {
+            false
+        } else if m <= HELD_KARP_MAX_NODES {
+            self.held_karp();
+            true
+        } else if m <= BRANCH_AND_BOUND_MAX_NODES {
+            self.branch_and_bound()
+        } else {
+            false
+        }
+    }
+
+    fn held_karp(&mut self) {
+        let order = self.order();
+        let m = order.len();
+        if m < 3 {
+            return;
+        }
+        // Stops other than the depot are numbered 0..k in the subset masks
+        let k = m - 1;
+        let full = 1usize << k;
+        let c = |a: usize, b: usize| self.arc(order[a], order[b]) as f64;
+        // cost[mask * k + j]: cheapest path from the depot through `mask`, ending at stop j
+        let mut cost = vec![f64::INFINITY; full * k];
+        let mut parent = vec![u8::MAX; full * k];
+        for j in 0..k {
+            cost[(1 << j) * k + j] = c(0, j + 1);
+        }
+        for mask in 1..full {
+            for j in 0..k {
+                let here = cost[mask * k + j];
+                if mask & (1 << j) == 0 || here == f64::INFINITY {
+                    continue;
+                }
+                for next in 0..k {
+                    if mask & (1 << next) != 0 {
+                        continue;
+                    }
+                    let slot = (mask | (1 << next)) * k + next;
+                    let candidate = here + c(j + 1, next + 1);
+                    if candidate < cost[slot] {
+                        cost[slot] = candidate;
+                        parent[slot] = j as u8;
+                    }
+                }
+            }
+        }
+
+        let all = full - 1;
+        let last = (0..k)
+            .min_by(|&x, &y| {
+                (cost[all * k + x] + c(x + 1, 0))
+                    .partial_cmp(&(cost[all * k + y] + c(y + 1, 0)))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap_or(0);
+        let mut path = Vec::with_capacity(m);
+        let (mut mask, mut j) = (all, last);
+        loop {
+            path.push(order[j + 1]);
+            let p = parent[mask * k + j];
+            mask ^= 1 << j;
+            if p == u8::MAX {
+                break;
+            }
+            j = p as usize;
+        }
+        path.push(order[0]);
+        path.reverse();
+        self.set_order(path);
+    }
+
+    /// Depth-first branch and bound over paths from the depot, starting from the current
+    /// route as upper bound. Returns `true` if the search finished.
+    fn branch_and_bound(&mut self) -> bool {
+        let order = self.order();
+        let m = order.len();
+        let cost: Vec<f64> = (0..m * m).map(|i| self.arc(order[i / m], order[i % m]) as f64).collect();
+        let sym: Vec<f64> = (0..m * m).map(|i| cost[i].min(cost[(i % m) * m + i / m])).collect();
+        let upper = self.order_cost(&order);
+        let (_, pi) = self.penalties(&sym, m, upper);
+        let mut search = BranchAndBound {
+            m,
+            sym,
+            pi,
+            cost,
+            best_cost: upper + EPS,
+            best: Vec::new(),
+            path: vec![0],
+            visited: vec![false; m],
+            expansions: 0,
+            aborted: false,
+            budget: &self.budget,
+        };
+        search.visited[0] = true;
+        search.extend(0.0);
+        let finished = !search.aborted;
+        if !search.best.is_empty() {
+            let best: Vec<usize> = search.best.iter().map(|&i| order[i]).collect();
+            self.set_order(best);
+        }
+        finished
+    }
+
+    /// Cost of the cycle `order`, including the edge back to its first node.
+    pub(crate) fn order_cost(&self, order: &[usize]) -> f64 {
+        let m = order.len();
+        (0..m).map(|i| self.arc(order[i], order[(i + 1) % m]) as f64).sum()
+    }
+
+    /// Held-Karp lower bound on the cost of any route through these stops: the best
+    /// 1-tree found by subgradient optimisation of node penalties, using the current
+    /// route's cost as the target. Asymmetric costs are relaxed to the cheaper direction
+    /// of each pair, so the bound stays valid for OSRM matrices and open paths. `None`
+    /// above `LOWER_BOUND_MAX_NODES` stops.
+    pub fn lower_bound(&mut self) -> Option<f32> {
+        let order = self.order();
+        let m = order.len();
+        if m > LOWER_BOUND_MAX_NODES {
+            return None;
+        }
+        self.distance_matrix();
+        let upper = self.order_cost(&order);
+        if m < 4 {
+            // Three stops or fewer: both directions of the only cycle are cheap to compare
+            let reversed: Vec<usize> = order.iter().rev().copied().collect();
+            return Some(upper.min(self.order_cost(&reversed)) as f32);
+        }
+        let cost: Vec<f64> = (0..m * m)
+            .map(|i| {
+                let (a, b) = (order[i / m], order[i % m]);
+                self.arc(a, b).min(self.arc(b, a)) as f64
+            })
+            .collect();
+        let (best, _) = self.penalties(&cost, m, upper);
+        Some(best.min(upper).max(0.0) as f32)
+    }
+
+    /// Subgradient optimisation of the node penalties of a 1-tree on the dense symmetric
+    /// `cost` matrix over `m` nodes, aiming at `upper`. Returns the best bound found and
+    /// the penalties that gave it.
+    fn penalties(&self, cost: &[f64], m: usize, upper: f64) -> (f64, Vec<f64>) {
+        let mut pi = vec![0.0f64; m];
+        let mut best = (f64::NEG_INFINITY, pi.clone());
+        let mut step_scale = 2.0;
+        let mut since_improvement = 0;
+        let iterations = LOWER_BOUND_ITERATIONS.min(LOWER_BOUND_WORK / (m * m)).max(10);
+        for _ in 0..iterations {
+            let (tree, degree) = one_tree(m, |a, b| cost[a * m + b] + pi[a] + pi[b]);
+            let bound = tree - 2.0 * pi.iter().sum::<f64>();
+            if bound > best.0 + EPS {
+                best = (bound, pi.clone());
+                since_improvement = 0;
+            } else {
+                since_improvement += 1;
+                if since_improvement >= (iterations / 10).max(2) {
+                    step_scale /= 2.0;
+                    since_improvement = 0;
+                }
+            }
+            // Every node of degree 2 means the 1-tree is itself a tour: the bound is tight
+            let norm: f64 = degree.iter().map(|&d| (d as f64 - 2.0).powi(2)).sum();
+            if norm == 0.0 || step_scale < 1e-3 || best.0 >= upper - EPS || self.budget.exhausted() {
+                break;
+            }
+            let step = step_scale * (upper - bound).max(EPS) / norm;
+            for (p, &d) in pi.iter_mut().zip(&degree) {
+                *p += step * (d as f64 - 2.0);
+            }
+        }
+        best
+    }
+
+    /// Lin-Kernighan: variable-depth search that chains 2-opt flips into sequential k-opt
+    /// moves, guided by the candidate lists. Runs until no improving chain is found.
+    pub fn lin_kernighan(&mut self) {
+        self.distance_matrix();
+        let order = self.order();
+        if order.len() < 4 {
+            return;
+        }
+        let cands = self.candidate_lists(&order, LK_CANDIDATES);
+        let mut lk = LkState::new(order.clone(), self.is_symmetric(&order));
+        let mut added = Vec::with_capacity(LK_MAX_DEPTH);
+        let mut improved = true;
+        while improved && !self.budget.exhausted() {
+            improved = false;
+            for &t1 in order.iter() {
+                if self.budget.exhausted() {
+                    break;
+                }
+                if self.lk_deepen(&mut lk, &cands, t1, 0, 0.0, -EPS, &mut added) {
+                    improved = true;
+                }
+            }
+        }
+        self.set_order(lk.order);
+    }
+
+    /// One level of the Lin-Kernighan chain. `delta` is the cost change of the flips applied
+    /// so far; returns `true` (leaving the tour modified) once the chain beats `threshold`,
+    /// otherwise undoes its own flips and returns `false`.
+    #[allow(clippy::too_many_arguments)]
+    fn lk_deepen(
+        &self,
+        lk: &mut LkState,
+        cands: &[Vec<usize>],
+        t1: usize,
+        depth: usize,
+        delta: f64,
+        threshold: f64,
+        added: &mut Vec<(usize, usize)>,
+    ) -> bool {
+        let t2 = lk.succ(t1);
+        // Gain still available if the closing edge t1 → t2 is broken next
+        let g_open = self.arc(t1, t2) as f64 - delta;
+        let mut moves: Vec<(f64, usize, usize)> = cands[t2]
+            .iter()
+            .filter_map(|&t3| {
+                if t3 == t1 || t3 == t2 {
+                    return None;
+                }
+                let t4 = lk.pred(t3);
+                // Never break an edge this chain has just added
+                if t4 == t2 || added.iter().any(|&e| e == (t4, t3) || e == (t3, t4)) {
+                    return None;
+                }
+                let g = g_open - self.arc(t2, t3) as f64;
+                if g <= EPS {
+                    return None;
+                }
+                Some((g + self.arc(t4, t3) as f64, t3, t4))
+            })
+            .collect();
+        moves.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let breadth = LK_BREADTH.get(depth).copied().unwrap_or(1);
+        for &(_, t3, t4) in moves.iter().take(breadth) {
+            let step = self.flip_delta(lk, t1, t2, t3, t4);
+            lk.flip(t2, t4);
+            let new_delta = delta + step;
+            // The chain may pass through orders that break the constraints, but only stops at one that keeps them
+            let improved_here = new_delta < threshold && self.keeps_order(lk);
+            let next_threshold = if improved_here { new_delta } else { threshold };
+            added.push((t2, t3));
+            let deeper = depth + 1 < LK_MAX_DEPTH
+                && self.lk_deepen(lk, cands, t1, depth + 1, new_delta, next_threshold, added);
+            added.pop();
+            if deeper || improved_here {
+                return true;
+            }
+            lk.flip(t4, t2);
+        }
+        false
+    }
+
+    /// Cost change of replacing edges t1 → t2 and t4 → t3 with t1 → t4 and t2 → t3,
+    /// which reverses the path t2 … t4. On asymmetric matrices the edges inside that path
+    /// change direction too, so their cost difference is walked and added.
+    fn flip_delta(&self, lk: &LkState, t1: usize, t2: usize, t3: usize, t4: usize) -> f64 {
+        let mut delta = self.arc(t1, t4) as f64 + self.arc(t2, t3) as f64
+            - self.arc(t1, t2) as f64
+            - self.arc(t4, t3) as f64;
+        if !lk.symmetric {
+            let mut a = t2;
+            while a != t4 {
+                let b = lk.succ(a);
+                delta += self.arc(b, a) as f64 - self.arc(a, b) as f64;
+                a = b;
+            }
+        }
+        delta
+    }
+
+    /// Whether the cycle `order` (see `order`) meets every order constraint.
+    fn respects_order(&self, order: &[usize]) -> bool {
+        if self.constraints.is_empty() {
+            return true;
+        }
+        let mut rank = vec![0; order.iter().max().map_or(0, |&m| m + 1)];
+        for (i, &id) in order.iter().enumerate() {
+            rank[id] = i;
+        }
+        self.constraints.precedence.iter().all(|&(a, b)| rank[a] < rank[b])
+            && self.constraints.pins.iter().all(|&(id, position)| rank[id] == position)
+    }
+
+    /// `respects_order` for a cycle held in an `LkState`, counting from the depot.
+    fn keeps_order(&self, lk: &LkState) -> bool {
+        let m = lk.order.len();
+        let rank = |id: usize| (lk.pos[id] + m - lk.pos[self.depot]) % m;
+        self.constraints.precedence.iter().all(|&(a, b)| rank(a) < rank(b))
+            && self.constraints.pins.iter().all(|&(id, position)| rank(id) == position)
+    }
+
+    /// Reorders the route as little as possible to meet the order constraints: stops keep
+    /// their current order except where one must wait for a stop it depends on or make
+    /// way for a pin.
+    fn repair_order(&mut self) {
+        let order = self.order();
+        if self.respects_order(&order) {
+            return;
+        }
+        let mut rank = vec![0; order.iter().max().map_or(0, |&m| m + 1)];
+        for (i, &id) in order.iter().enumerate() {
+            rank[id] = i;
+        }
+        let repaired = self.lay_out(|_, ready| *ready.iter().min_by_key(|&&id| rank[id]).expect("ready is not empty"));
+        self.set_order(repaired);
+    }
+
+    /// A cycle from the depot meeting the order constraints, built with
+    /// `OrderConstraints::schedule`, falling back to deadline-first when `choose` alone
+    /// strands a pin.
+    fn lay_out(&self, mut choose: impl FnMut(usize, &[usize]) -> usize) -> Vec<usize> {
+        let order = self.order();
+        let laid = self.constraints.schedule(self.depot, &order[1..], false, &mut choose)
+            .or_else(|_| self.constraints.schedule(self.depot, &order[1..], true, &mut choose))
+            .expect("order constraints are checked when set");
+        std::iter::once(self.depot).chain(laid).collect()
+    }
+
+    /// Whether every edge between `ids` costs the same in both directions. Haversine
+    /// closed loops always are; open paths are not because of the depot's edges.
+    fn is_symmetric(&self, ids: &[usize]) -> bool {
+        ids.iter().enumerate()
+            .all(|(i, &a)| ids[i + 1..].iter().all(|&b| self.arc(a, b) == self.arc(b, a)))
+    }
+
+    /// For every node in `ids`, the `k` other nodes of `ids` that are cheapest to travel to.
+    /// Indexed by node id.
+    fn candidate_lists(&self, ids: &[usize], k: usize) -> Vec<Vec<usize>> {
+        let size = ids.iter().max().map_or(0, |&m| m + 1);
+        let mut lists = vec![Vec::new(); size];
+        for &a in ids {
+            let mut others: Vec<usize> = ids.iter().copied().filter(|&b| b != a).collect();
+            let closer = |x: &usize, y: &usize| {
+                self.arc(a, *x).partial_cmp(&self.arc(a, *y)).unwrap_or(std::cmp::Ordering::Equal)
+            };
+            if others.len() > k {
+                others.select_nth_unstable_by(k, closer);
+                others.truncate(k);
+            }
+            others.sort_by(closer);
+            lists[a] = others;
+        }
+        lists
+    }
+
+    /// Directed cost of travelling `from → to` in the solver's cycle, where edges into and
+    /// out of the depot stand for reaching `end` and leaving `start` (see `set_endpoints`).
+    /// Requires `distance_matrix()` to have run.
+    fn arc(&self, from: usize, to: usize) -> f32 {
+        if to == self.depot {
+            self.end.map_or(0.0, |e| self.distance.cost(from, e))
+        } else if from == self.depot {
+            self.start.map_or(0.0, |s| self.distance.cost(s, to))
+        } else {
+            self.distance.cost(from, to)
+        }
+    }
+
+    /// The route as the cycle the solvers work on: the depot followed by every stop that
+    /// is not a fixed endpoint, in visiting order.
+    pub(crate) fn order(&self) -> Vec<usize> {
+        std::iter::once(self.depot)
+            .chain(
+                self.route.iter()
+                    .map(|n| n.id)
+                    .filter(|&id| id != self.depot && Some(id) != self.start && Some(id) != self.end),
+            )
+            .collect()
+    }
+
+    /// Rebuilds `route` from a cycle of node ids (see `order`), rotated so the depot comes
+    /// first, adding the fixed endpoints back.
+    pub(crate) fn set_order(&mut self, mut order: Vec<usize>) {
+        if let Some(p) = order.iter().position(|&id| id == self.depot) {
+            order.rotate_left(p);
+        }
+        self.route = self.path_ids(&order).iter().map(|&id| self.nodes[id].clone()).collect();
+    }
+
+    /// Route node ids for a cycle that starts at the depot, with the fixed endpoints added.
+    fn path_ids(&self, order: &[usize]) -> Vec<usize> {
+        self.start.into_iter()
+            .chain(order[1..].iter().copied())
+            .chain(self.end)
+            .collect()
+    }
+
+}
+
+/// Cells per side of the grid `space_filling_curve_tour` maps positions onto.
+const HILBERT_SIDE: u32 = 1 << 16;
+/// Largest set of odd-degree stops Christofides matches exactly (bitmask DP).
+const EXACT_MATCHING_MAX: usize = 18;
+
+/// Distance of cell (x, y) along the Hilbert curve filling a `HILBERT_SIDE`² grid.
+fn hilbert_index(mut x: u32, mut y: u32) -> u64 {
+    let mut d = 0u64;
+    let mut s = HILBERT_SIDE / 2;
+    while s > 0 {
+        let rx = u32::from(x & s > 0);
+        let ry = u32::from(y & s > 0);
+        d += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // Rotate the quadrant so the curve stays continuous
+        if ry == 0 {
+            if rx == 1 {
+                x = HILBERT_SIDE - 1 - x;
+                y = HILBERT_SIDE - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+/// Convex hull of the nodes `ids` (Andrew's monotone chain on their positions), in
+/// counter-clockwise order. Collinear points are left for insertion.
+fn convex_hull(ids: &[usize], nodes: &[Node]) -> Vec<usize> {
+    let mut points = ids.to_vec();
+    points.sort_by(|&a, &b| {
+        (nodes[a].x, nodes[a].y).partial_cmp(&(nodes[b].x, nodes[b].y)).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    points.dedup_by(|a, b| (nodes[*a].x, nodes[*a].y) == (nodes[*b].x, nodes[*b].y));
+    if points.len() < 3 {
+        return points;
+    }
+    let cross = |o: usize, a: usize, b: usize| {
+        let (o, a, b) = (&nodes[o], &nodes[a], &nodes[b]);
+        (a.x - o.x) as f64 * (b.y - o.y) as f64 - (a.y - o.y) as f64 * (b.x - o.x) as f64
+    };
+    let mut hull: Vec<usize> = Vec::with_capacity(2 * points.len());
+    for pass in [points.clone(), points.iter().rev().copied().collect()] {
+        let floor = hull.len();
+        for p in pass {
+            while hull.len() >= floor + 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0 {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        hull.pop();
+    }
+    hull
+}
+
+/// Minimum-weight perfect matching of `odd` (local indices, even count) under `w`:
+/// exact bitmask dynamic programming for small sets, cheapest-pair-first beyond.
+fn min_weight_matching(odd: &[usize], w: &impl Fn(usize, usize) -> f64) -> Vec<(usize, usize)> {
+    let k = odd.len();
+    if k <= EXACT_MATCHING_MAX {
+        let full = (1usize << k) - 1;
+        let mut cost = vec![f64::INFINITY; full + 1];
+        let mut pick = vec![(0, 0); full + 1];
+        cost[0] = 0.0;
+        for mask in 0..full {
+            if cost[mask] == f64::INFINITY {
+                continue;
+            }
+            // Always pair the lowest unmatched stop, so each matching is built once
+            let i = (!mask).trailing_zeros() as usize;
+            for j in i + 1..k {
+                if mask & (1 << j) == 0 {
+                    let next = mask | (1 << i) | (1 << j);
+                    let c = cost[mask] + w(odd[i], odd[j]);
+                    if c < cost[next] {
+                        cost[next] = c;
+                        pick[next] = (i, j);
+                    }
+                }
+            }
+        }
+        let mut pairs = Vec::with_capacity(k / 2);
+        let mut mask = full;
+        while mask != 0 {
+            let (i, j) = pick[mask];
+            pairs.push((odd[i], odd[j]));
+            mask &= !((1 << i) | (1 << j));
+        }
+        return pairs;
+    }
+    let mut candidates: Vec<(f64, usize, usize)> = (0..k)
+        .flat_map(|i| (i + 1..k).map(move |j| (i, j)))
+        .map(|(i, j)| (w(odd[i], odd[j]), odd[i], odd[j]))
+        .collect();
+    candidates.sort_by(|x, y| x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Equal));
+    let mut matched = std::collections::HashSet::new();
+    let mut pairs = Vec::with_capacity(k / 2);
+    for (_, a, b) in candidates {
+        if !matched.contains(&a) && !matched.contains(&b) {
+            matched.insert(a);
+            matched.insert(b);
+            pairs.push((a, b));
+        }
+    }
+    pairs
+}
+
+/// Minimum 1-tree over `m` nodes with symmetric edge weights `w`: a spanning tree over
+/// nodes 1.. (Prim) plus the two cheapest edges at node 0. Returns the weight and every
+/// node's degree.
+fn one_tree(m: usize, w: impl Fn(usize, usize) -> f64) -> (f64, Vec<usize>) {
+    let mut degree = vec![0; m];
+    let mut in_tree = vec![false; m];
+    let mut reach = vec![f64::INFINITY; m];
+    let mut parent = vec![1; m];
+    reach[1] = 0.0;
+    let mut total = 0.0;
+    for _ in 1..m {
+        let Some(u) = (1..m)
+            .filter(|&v| !in_tree[v])
+            .min_by(|&a, &b| reach[a].total_cmp(&reach[b]))
+        else {
+            break;
+        };
+        in_tree[u] = true;
+        total += reach[u];
+        if u != 1 {
+            degree[u] += 1;
+            degree[parent[u]] += 1;
+        }
+        for v in 1..m {
+            if !in_tree[v] && w(u, v) < reach[v] {
+                reach[v] = w(u, v);
+                parent[v] = u;
+            }
+        }
+    }
+    let mut to_root: Vec<usize> = (1..m).collect();
+    to_root.sort_by(|&a, &b| w(0, a).total_cmp(&w(0, b)));
+    for &v in to_root.iter().take(2) {
+        total += w(0, v);
+        degree[0] += 1;
+        degree[v] += 1;
+    }
+    (total, degree)
+}
+
+/// Search state for `Tour::branch_and_bound`, on local indices where 0 is the depot.
+struct BranchAndBound<'a> {
+    m: usize,
+    /// Directed costs, `cost[a * m + b]`.
+    cost: Vec<f64>,
+    /// Cheaper direction of each pair, for the 1-tree bound.
+    sym: Vec<f64>,
+    /// Node penalties optimised for the 1-tree bound at the root.
+    pi: Vec<f64>,
+    best_cost: f64,
+    best: Vec<usize>,
+    path: Vec<usize>,
+    visited: Vec<bool>,
+    expansions: usize,
+    aborted: bool,
+    budget: &'a Budget,
+}
+
+impl BranchAndBound<'_> {
+    fn extend(&mut self, so_far: f64) {
+        let m = self.m;
+        let last = *self.path.last().unwrap_or(&0);
+        if self.path.len() == m {
+            let total = so_far + self.cost[last * m];
+            if total < self.best_cost - EPS {
+                self.best_cost = total;
+                self.best = self.path.clone();
+            }
+            return;
+        }
+        self.expansions += 1;
+        if self.expansions > BRANCH_AND_BOUND_MAX_EXPANSIONS
+            || (self.expansions.is_multiple_of(1024) && self.budget.exhausted())
+        {
+            self.aborted = true;
+            return;
+        }
+        if so_far + self.one_tree_bound(last) >= self.best_cost - EPS {
+            return;
+        }
+        let mut next: Vec<usize> = (0..m).filter(|&v| !self.visited[v]).collect();
+        next.sort_by(|&a, &b| self.cost[last * m + a].total_cmp(&self.cost[last * m + b]));
+        for v in next {
+            self.visited[v] = true;
+            self.path.push(v);
+            self.extend(so_far + self.cost[last * m + v]);
+            self.path.pop();
+            self.visited[v] = false;
+            if self.aborted {
+                return;
+            }
+        }
+    }
+
+    /// The rest of the route leaves `last`, runs a Hamiltonian path through the unvisited
+    /// stops and returns to the depot. Bounded by a 1-tree with the root's penalties over
+    /// the unvisited stops and the visited path contracted into node 0, which reaches a
+    /// stop by the cheaper of leaving `last` and entering the depot. Every unvisited stop
+    /// has degree 2 on the rest of the route, so the penalties cancel out of its cost.
+    fn one_tree_bound(&self, last: usize) -> f64 {
+        let m = self.m;
+        let nodes: Vec<usize> = std::iter::once(0).chain((0..m).filter(|&v| !self.visited[v])).collect();
+        let (tree, _) = one_tree(nodes.len(), |i, j| {
+            let (a, b) = (nodes[i], nodes[j]);
+            match (i, j) {
+                (0, _) => self.cost[last * m + b].min(self.cost[b * m]) + self.pi[b],
+                (_, 0) => self.cost[last * m + a].min(self.cost[a * m]) + self.pi[a],
+                _ => self.sym[a * m + b] + self.pi[a] + self.pi[b],
+            }
+        });
+        tree - 2.0 * nodes[1..].iter().map(|&v| self.pi[v]).sum::<f64>()
+    }
+}
+
+/// Lexicographic comparison of (lateness, finishing time) scores.
+fn beats(a: (f64, f64), b: (f64, f64)) -> bool {
+    a.0 < b.0 - EPS || (a.0 <= b.0 + EPS && a.1 < b.1 - EPS)
+}
+
+/// Array representation of a cyclic tour used by Lin-Kernighan, with O(1) position lookups.
+struct LkState {
+    order: Vec<usize>,
+    pos: Vec<usize>,
+    /// Skips walking reversed segments when computing flip deltas.
+    symmetric: bool,
+}
+
+impl LkState {
+    fn new(order: Vec<usize>, symmetric: bool) -> Self {
+        let mut pos = vec![0; order.iter().max().map_or(0, |&m| m + 1)];
+        for (i, &id) in order.iter().enumerate() {
+            pos[id] = i;
+        }
+        Self { order, pos, symmetric }
+    }
+
+    fn succ(&self, id: usize) -> usize {
+        self.order[(self.pos[id] + 1) % self.order.len()]
+    }
+
+    fn pred(&self, id: usize) -> usize {
+        let m = self.order.len();
+        self.order[(self.pos[id] + m - 1) % m]
+    }
+
+    /// Same as `flip`, but on symmetric costs reverses the complementary path when that
+    /// is shorter, which gives the same cycle walked the other way round.
+    fn flip_shorter(&mut self, from: usize, to: usize) {
+        let m = self.order.len();
+        let len = (self.pos[to] + m - self.pos[from]) % m + 1;
+        if self.symmetric && 2 * len > m && len < m {
+            self.flip(self.succ(to), self.pred(from));
+        } else {
+            self.flip(from, to);
+        }
+    }
+
+    /// Moves `segment` (consecutive ids, in either order) to just after `after`, in the
+    /// order given.
+    fn relocate(&mut self, segment: &[usize], after: usize) {
+        let mut order = Vec::with_capacity(self.order.len());
+        for &id in &self.order {
+            if segment.contains(&id) {
+                continue;
+            }
+            order.push(id);
+            if id == after {
+                order.extend_from_slice(segment);
+            }
+        }
+        for (i, &id) in order.iter().enumerate() {
+            self.pos[id] = i;
+        }
+        self.order = order;
+    }
+
+    /// Rebuilds the cycle a S1 S2 f, with S1 = b..c and S2 = d..e, as one of its seven
+    /// 3-opt reconnections: 0 a S1' S2 f, 1 a S1 S2' f, 2 a S2' S1' f, 3 a S2 S1 f,
+    /// 4 a S2 S1' f, 5 a S2' S1 f, 6 a S1' S2' f, where ' marks a reversed segment.
+    fn reconnect(&mut self, [a, b, c, d, e, f]: [usize; 6], reconnection: usize) {
+        let path = |lk: &Self, from: usize, to: usize| {
+            let mut ids = vec![from];
+            while *ids.last().expect("path is not empty") != to {
+                ids.push(lk.succ(*ids.last().expect("path is not empty")));
+            }
+            ids
+        };
+        let (s1, s2) = (path(self, b, c), path(self, d, e));
+        let rev = |s: &[usize]| s.iter().rev().copied().collect::<Vec<usize>>();
+        let middle = match reconnection {
+            0 => [rev(&s1), s2],
+            1 => [s1, rev(&s2)],
+            2 => [rev(&s2), rev(&s1)],
+            3 => [s2, s1],
+            4 => [s2, rev(&s1)],
+            5 => [rev(&s2), s1],
+            _ => [rev(&s1), rev(&s2)],
+        }
+        .concat();
+        // f … a is untouched (f == a when the three edges cover the whole cycle)
+        let mut order = if f == a { vec![a] } else { path(self, f, a) };
+        order.extend(middle);
+        for (i, &id) in order.iter().enumerate() {
+            self.pos[id] = i;
+        }
+        self.order = order;
+    }
+
+    /// Reverses the path that runs forward from `from` to `to`, wrapping around the array.
+    fn flip(&mut self, from: usize, to: usize) {
+        let m = self.order.len();
+        let (i, j) = (self.pos[from], self.pos[to]);
+        let len = (j + m - i) % m + 1;
+        for s in 0..len / 2 {
+            let a = (i + s) % m;
+            let b = (j + m - s) % m;
+            self.order.swap(a, b);
+            self.pos[self.order[a]] = a;
+            self.pos[self.order[b]] = b;
+        }
+    }
+}
+
+/// Don't-look bits as a work queue: only nodes near a recent change are looked at again.
+struct DontLook {
+    queue: VecDeque<usize>,
+    queued: Vec<bool>,
+}
+
+impl DontLook {
+    /// Starts with every node in `ids` active.
+    fn new(ids: &[usize]) -> Self {
+        let mut queued = vec![false; ids.iter().max().map_or(0, |&m| m + 1)];
+        for &id in ids {
+            queued[id] = true;
+        }
+        Self { queue: ids.iter().copied().collect(), queued }
+    }
+
+    fn next(&mut self) -> Option<usize> {
+        let id = self.queue.pop_front()?;
+        self.queued[id] = false;
+        Some(id)
+    }
+
+    fn wake(&mut self, ids: &[usize]) {
+        for &id in ids {
+            if !self.queued[id] {
+                self.queued[id] = true;
+                self.queue.push_back(id);
+            }
+        }
+    }
+}
+
+pub fn haversine_km(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    const R: f64 = 6371.0;
+    let dlat = (lat2 - lat1).to_radians();
+    let dlng = (lng2 - lng1).to_radians();
+    let a = (dlat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos()
+        * (dlng / 2.0).sin().powi(2);
+    R * 2.0 * a.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ── Haversine Distance Tests ───────────────────────────────────────────────
+
+    #[test]
+    fn haversine_london_to_paris() {
+        // London (51.5074, -0.1278) → Paris (48.8566, 2.3522) ≈ 341 km
+        let d = haversine_km(51.5074, -0.1278, 48.8566, 2.3522);
+        assert!((d - 341.0).abs() < 5.0, "expected ~341 km, got {:.1}", d);
+    }
+
+    #[test]
+    fn haversine_zero_distance() {
         // Same point should be ~0 km
         let d = haversine_km(51.5, -0.1, 51.5, -0.1);
         assert!(d < 0.001, "expected ~0 km, got {:.6}", d);
     }
 
     #[test]
-    fn haversine_symmetry() {
-        // Distance A → B should equal B → A
-        let d1 = haversine_km(51.5, -0.1, 48.8, 2.3);
-        let d2 = haversine_km(48.8, 2.3, 51.5, -0.1);
-        assert!((d1 - d2).abs() < 0.01, "symmetry failed: {:.2} vs {:.2}", d1, d2);
+    fn haversine_symmetry() {
+        // Distance A → B should equal B → A
+        let d1 = haversine_km(51.5, -0.1, 48.8, 2.3);
+        let d2 = haversine_km(48.8, 2.3, 51.5, -0.1);
+        assert!((d1 - d2).abs() < 0.01, "symmetry failed: {:.2} vs {:.2}", d1, d2);
+    }
+
+    #[test]
+    fn haversine_santiago_to_valparaiso() {
+        // Santiago (-33.87, -70.72) → Valparaíso (-33.04, -71.63) ≈ 125 km
+        let d = haversine_km(-33.87, -70.72, -33.04, -71.63);
+        assert!((d - 125.0).abs() < 10.0, "expected ~125 km, got {:.1}", d);
+    }
+
+    // ── Tour Construction Tests ────────────────────────────────────────────────
+
+    #[test]
+    fn tour_new_creates_closed_loop() {
+        // Tour with 3 nodes should have route[0] == route[last]
+        let positions = vec![(0.0, 0.0), (10.0, 0.0), (5.0, 5.0)];
+        let tour = Tour::new(positions);
+        assert_eq!(tour.route.len(), 4, "route should have 4 nodes (3 + 1 closing)");
+        assert_eq!(tour.route[0].id, tour.route[3].id);
+    }
+
+    #[test]
+    fn tour_new_initializes_nodes() {
+        let positions = vec![(1.0, 2.0), (3.0, 4.0)];
+        let tour = Tour::new(positions);
+        assert_eq!(tour.nodes.len(), 2);
+        assert_eq!(tour.nodes[0].x, 1.0);
+        assert_eq!(tour.nodes[0].y, 2.0);
+    }
+
+    #[test]
+    fn tour_calculate_cost_single_point() {
+        let positions = vec![(0.0, 0.0)];
+        let mut tour = Tour::new(positions);
+        tour.calculate_cost();
+        assert!(tour.cost < 0.01, "single node cost should be ~0, got {}", tour.cost);
+    }
+
+    #[test]
+    fn tour_calculate_cost_triangle() {
+        // 3 nodes in a right triangle: (0,0) → (3,0) → (0,4) → (0,0)
+        // Distances: 3 + 5 + 4 = 12 (Haversine approximates Euclidean for small distances)
+        let positions = vec![(0.0, 0.0), (0.003, 0.0), (0.0, 0.004)];
+        let mut tour = Tour::new(positions);
+        tour.calculate_cost();
+        // Cost should be positive (sum of edge weights)
+        assert!(tour.cost > 0.0, "cost should be positive, got {}", tour.cost);
+    }
+
+    #[test]
+    fn tour_distance_matrix_symmetric() {
+        let positions = vec![(0.0, 0.0), (10.0, 0.0), (5.0, 5.0)];
+        let mut tour = Tour::new(positions);
+        tour.distance_matrix();
+
+        // Distance[i][j] should equal distance[j][i]
+        for i in 0..tour.nodes.len() {
+            for j in 0..tour.nodes.len() {
+                let d_ij = tour.distance.cost(i, j);
+                let d_ji = tour.distance.cost(j, i);
+                assert_eq!(d_ij, d_ji, "asymmetric distance: [{},{}]={:?} vs [{},{}]={:?}", i, j, d_ij, j, i, d_ji);
+            }
+        }
+    }
+
+    #[test]
+    fn tour_distance_matrix_diagonal_zero() {
+        let positions = vec![(0.0, 0.0), (10.0, 0.0), (5.0, 5.0)];
+        let mut tour = Tour::new(positions);
+        tour.distance_matrix();
+
+        // Distance from a node to itself should be ~0
+        for i in 0..tour.nodes.len() {
+            let d = tour.distance.cost(i, i);
+            assert!(d < 0.01, "diagonal distance should be ~0, got {}", d);
+        }
+    }
+
+    // ── Nearest Neighbour Algorithm Tests ──────────────────────────────────────
+
+    #[test]
+    fn nearest_neighbour_visits_all_nodes() {
+        let positions = vec![(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)];
+        let mut tour = Tour::new(positions);
+        tour.nearest_neighbour_tour();
+
+        // Route should have n+1 nodes (n cities + 1 return to start)
+        assert_eq!(tour.route.len(), 5, "route should visit all 4 nodes + return");
+
+        // Should start and end at node 0
+        assert_eq!(tour.route[0].id, 0);
+        assert_eq!(tour.route[4].id, 0);
+
+        // Should visit all intermediate nodes once
+        let mut visited = vec![false; tour.nodes.len()];
+        for i in 0..tour.route.len() - 1 {
+            visited[tour.route[i].id] = true;
+        }
+        for v in visited.iter() {
+            assert!(v, "not all nodes visited");
+        }
+    }
+
+    #[test]
+    fn nearest_neighbour_start_is_node_zero() {
+        let positions = vec![(0.0, 0.0), (100.0, 100.0), (10.0, 10.0)];
+        let mut tour = Tour::new(positions);
+        tour.nearest_neighbour_tour();
+        assert_eq!(tour.route[0].id, 0, "should start from node 0");
+    }
+
+    #[test]
+    fn nearest_neighbour_three_nodes() {
+        let positions = vec![(0.0, 0.0), (1.0, 0.0), (0.5, 0.5)];
+        let mut tour = Tour::new(positions);
+        tour.nearest_neighbour_tour();
+
+        // Should have visited all 3 nodes + return to start
+        assert_eq!(tour.route.len(), 4);
+        assert_eq!(tour.route[0].id, 0);
+        assert_eq!(tour.route[3].id, 0);
+
+        // All nodes should be present in route[0..3]
+        // (order depends on which neighbor is nearest from each node)
+        let visited: Vec<usize> = tour.route[1..3].iter().map(|n| n.id).collect();
+        assert!(visited.contains(&1));
+        assert!(visited.contains(&2));
+    }
+
+    // ── Construction Heuristic Tests ──────────────────────────────────────────
+
+    const BUILT_CONSTRUCTIONS: [Construction; 6] = [
+        Construction::CheapestInsertion, Construction::FarthestInsertion,
+        Construction::ConvexHullInsertion, Construction::GreedyEdge,
+        Construction::SpaceFillingCurve, Construction::Christofides,
+    ];
+
+    #[test]
+    fn construction_named_should_know_every_name() {
+        for name in Construction::NAMES {
+            assert!(Construction::named(name).is_some(), "missing construction {}", name);
+        }
+        assert_eq!(Construction::named("christofides"), Some(Construction::Christofides));
+        assert!(Construction::named("savings").is_none());
+    }
+
+    #[test]
+    fn constructions_should_visit_every_node_once() {
+        for construction in BUILT_CONSTRUCTIONS {
+            let mut tour = Tour::create_random_nodes_with_seed(40, 0.5, 0.5, 8);
+            tour.construct(construction);
+            let ids = route_ids(&tour);
+            assert_eq!((ids[0], ids[40]), (0, 0), "{:?} moved the depot", construction);
+            let mut sorted = ids[..40].to_vec();
+            sorted.sort();
+            assert_eq!(sorted, (0..40).collect::<Vec<_>>(), "{:?} lost nodes", construction);
+        }
+    }
+
+    #[test]
+    fn constructions_should_keep_open_path_endpoints() {
+        for construction in BUILT_CONSTRUCTIONS {
+            for (start, end) in [(Some(1), Some(4)), (None, Some(2)), (None, None)] {
+                let mut tour = Tour::new(line_positions());
+                tour.set_endpoints(start, end);
+                tour.construct(construction);
+                let ids = route_ids(&tour);
+                assert_eq!(ids.len(), 5, "{:?} with {:?}", construction, (start, end));
+                assert!(start.is_none_or(|s| ids[0] == s) && end.is_none_or(|e| ids[4] == e),
+                    "{:?} broke endpoints {:?}: {:?}", construction, (start, end), ids);
+            }
+        }
+    }
+
+    #[test]
+    fn christofides_should_stay_within_one_and_a_half_of_optimal() {
+        for seed in 0..3 {
+            let mut exact = Tour::create_random_nodes_with_seed(11, 0.5, 0.5, seed);
+            exact.solve_exact();
+            exact.calculate_cost();
+            let mut tour = Tour::create_random_nodes_with_seed(11, 0.5, 0.5, seed);
+            tour.christofides_tour();
+            tour.calculate_cost();
+            assert!(tour.cost <= exact.cost * 1.5 + 1e-3, "{} vs optimum {}", tour.cost, exact.cost);
+        }
+    }
+
+    #[test]
+    fn constructions_should_beat_random_routes() {
+        let mut random = Tour::create_random_nodes_with_seed(200, 0.5, 0.5, 2);
+        random.random_tour();
+        random.calculate_cost();
+        for construction in BUILT_CONSTRUCTIONS {
+            let mut tour = Tour::create_random_nodes_with_seed(200, 0.5, 0.5, 2);
+            tour.construct(construction);
+            tour.calculate_cost();
+            assert!(tour.cost < random.cost / 3.0, "{:?}: {} vs random {}", construction, tour.cost, random.cost);
+        }
+    }
+
+    #[test]
+    fn convex_hull_should_return_outer_points_in_order() {
+        let nodes: Vec<Node> = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.5, 0.5)]
+            .iter().enumerate().map(|(id, &(x, y))| Node { id, x, y }).collect();
+        assert_eq!(convex_hull(&[0, 1, 2, 3, 4], &nodes), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn min_weight_matching_should_pair_nearest_points() {
+        let points = [0.0f64, 1.0, 10.0, 11.0];
+        let w = |a: usize, b: usize| (points[a] - points[b]).abs();
+        let mut pairs = min_weight_matching(&[0, 1, 2, 3], &w);
+        pairs.sort();
+        assert_eq!(pairs, vec![(0, 1), (2, 3)]);
+    }
+
+    // ── 2-Opt Algorithm Tests ──────────────────────────────────────────────────
+
+    #[test]
+    fn two_opt_does_not_worsen_cost() {
+        let positions = vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
+        let mut tour = Tour::new(positions);
+        tour.random_tour();
+        tour.calculate_cost();
+        let cost_before = tour.cost;
+
+        tour.two_opt();
+        tour.calculate_cost();
+        let cost_after = tour.cost;
+
+        assert!(cost_after <= cost_before + 0.1, "2-opt made route worse: {} → {}", cost_before, cost_after);
+    }
+
+    #[test]
+    fn two_opt_preserves_all_nodes() {
+        let positions = vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
+        let mut tour = Tour::new(positions);
+        tour.random_tour();
+        tour.two_opt();
+
+        // All nodes should still be in route (except closing duplicate)
+        assert_eq!(tour.route.len(), 5);
+        assert_eq!(tour.route[0].id, tour.route[4].id);
+    }
+
+    // ── Or-Opt Algorithm Tests ────────────────────────────────────────────────
+
+    #[test]
+    fn or_opt_preserves_route_length() {
+        let positions = vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (5.0, 5.0)];
+        let mut tour = Tour::new(positions);
+        tour.random_tour();
+        let len_before = tour.route.len();
+
+        tour.or_opt();
+
+        assert_eq!(tour.route.len(), len_before, "or_opt changed route length");
+    }
+
+    #[test]
+    fn or_opt_keeps_all_nodes() {
+        let positions = vec![(0.0, 0.0), (1.0, 1.0), (2.0, 0.0), (1.0, -1.0)];
+        let mut tour = Tour::new(positions);
+        tour.random_tour();
+
+        // Record original nodes
+        let original_ids: Vec<usize> = tour.route[..tour.route.len() - 1]
+            .iter()
+            .map(|n| n.id)
+            .collect();
+
+        tour.or_opt();
+
+        // Check all nodes still present (minus the closing duplicate)
+        let final_ids: Vec<usize> = tour.route[..tour.route.len() - 1]
+            .iter()
+            .map(|n| n.id)
+            .collect();
+
+        assert_eq!(final_ids.len(), original_ids.len());
+        for id in original_ids.iter() {
+            assert!(final_ids.contains(id), "node {} missing after or_opt", id);
+        }
+    }
+
+    #[test]
+    fn or_opt_does_not_worsen_cost() {
+        let positions = vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (5.0, 5.0)];
+        let mut tour = Tour::new(positions);
+        tour.random_tour();
+        tour.calculate_cost();
+        let cost_before = tour.cost;
+
+        tour.or_opt();
+        tour.calculate_cost();
+        let cost_after = tour.cost;
+
+        assert!(cost_after <= cost_before + 0.1, "or_opt made route worse: {} → {}", cost_before, cost_after);
+    }
+
+    // ── Candidate List Tests ──────────────────────────────────────────────────
+
+    #[test]
+    fn local_search_should_scale_to_large_instances() {
+        let mut tour = Tour::create_random_nodes_with_seed(1500, 0.5, 0.5, 4);
+        tour.nearest_neighbour_tour();
+        tour.calculate_cost();
+        let nearest = tour.cost;
+        tour.two_opt();
+        tour.or_opt();
+        tour.calculate_cost();
+        assert!(tour.cost < nearest * 0.95, "expected a clear gain over {}, got {}", nearest, tour.cost);
+        let mut ids = route_ids(&tour)[..1500].to_vec();
+        ids.sort();
+        assert_eq!(ids, (0..1500).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn candidate_lists_should_hold_nearest_first() {
+        let mut tour = Tour::new(line_positions());
+        tour.distance_matrix();
+        let lists = tour.candidate_lists(&[0, 1, 2, 3, 4], 2);
+        // Along the line the stops sit at 0.00, 0.03, 0.01, 0.04 and 0.02
+        assert_eq!(lists[0], vec![2, 4]);
+        assert_eq!(lists[3], vec![1, 4]);
+    }
+
+    #[test]
+    fn dont_look_should_requeue_only_woken_nodes() {
+        let mut active = DontLook::new(&[0, 1, 2]);
+        assert_eq!((active.next(), active.next()), (Some(0), Some(1)));
+        active.wake(&[0, 2]);
+        assert_eq!((active.next(), active.next(), active.next()), (Some(2), Some(0), None));
+    }
+
+    #[test]
+    fn relocate_should_move_segment_after_node() {
+        let mut lk = LkState::new(vec![0, 1, 2, 3, 4, 5], true);
+        lk.relocate(&[1, 2], 4);
+        assert_eq!(lk.order, vec![0, 3, 4, 1, 2, 5]);
+        assert_eq!((lk.succ(4), lk.pred(3)), (1, 0));
+    }
+
+    // ── 3-Opt Tests ───────────────────────────────────────────────────────────
+
+    fn circle_positions(n: usize) -> Vec<(f32, f32)> {
+        (0..n)
+            .map(|i| {
+                let angle = i as f32 / n as f32 * std::f32::consts::TAU;
+                (0.01 * angle.cos(), 0.01 * angle.sin())
+            })
+            .collect()
+    }
+
+    fn tour_along(positions: Vec<(f32, f32)>, ids: &[usize]) -> Tour {
+        let mut tour = Tour::new(positions);
+        tour.set_order(ids.to_vec());
+        tour.calculate_cost();
+        tour
+    }
+
+    fn circle_cost(n: usize) -> f32 {
+        tour_along(circle_positions(n), &(0..n).collect::<Vec<_>>()).cost
+    }
+
+    #[test]
+    fn three_opt_should_swap_adjacent_segments() {
+        // 4..=8 and 9..=13 are swapped; fixing it takes a pure segment insertion
+        let ids = [0, 1, 2, 3, 9, 10, 11, 12, 13, 4, 5, 6, 7, 8, 14, 15];
+        let mut tour = tour_along(circle_positions(16), &ids);
+        tour.three_opt();
+        tour.calculate_cost();
+        assert!((tour.cost - circle_cost(16)).abs() < 1e-3, "{} vs {}", tour.cost, circle_cost(16));
+    }
+
+    #[test]
+    fn three_opt_should_not_worsen_asymmetric_routes() {
+        let matrix = scrambled_matrix(30, 12);
+        let mut tour = Tour::with_matrix(positions_for(30), matrix).unwrap();
+        tour.set_seed(3);
+        tour.random_tour();
+        tour.calculate_cost();
+        let before = tour.cost;
+        tour.three_opt();
+        tour.calculate_cost();
+        assert!(tour.cost < before, "{} not below {}", tour.cost, before);
+        let mut ids = route_ids(&tour)[..30].to_vec();
+        ids.sort();
+        assert_eq!(ids, (0..30).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reconnect_should_build_each_case() {
+        let expected = [
+            vec![0, 2, 1, 3, 4, 5],
+            vec![0, 1, 2, 4, 3, 5],
+            vec![0, 4, 3, 2, 1, 5],
+            vec![0, 3, 4, 1, 2, 5],
+            vec![0, 3, 4, 2, 1, 5],
+            vec![0, 4, 3, 1, 2, 5],
+            vec![0, 2, 1, 4, 3, 5],
+        ];
+        for (case, want) in expected.iter().enumerate() {
+            let mut lk = LkState::new(vec![0, 1, 2, 3, 4, 5], true);
+            lk.reconnect([0, 1, 2, 3, 4, 5], case);
+            // Rotate so node 0 leads, as the cycle may start anywhere
+            let p = lk.order.iter().position(|&x| x == 0).unwrap();
+            lk.order.rotate_left(p);
+            assert_eq!(&lk.order, want, "case {}", case);
+        }
+    }
+
+    #[test]
+    fn or_opt_should_leave_no_improving_reversed_insertion() {
+        // Seed where forward-only relocation gets stuck well above the reversed version
+        let mut tour = Tour::create_random_nodes_with_seed(9, 0.5, 0.5, 44);
+        tour.random_tour();
+        tour.or_opt();
+        let cycle: Vec<usize> = route_ids(&tour)[..9].to_vec();
+        let cost = |ids: &[usize]| -> f32 {
+            (0..ids.len()).map(|i| tour.travel_cost(ids[i], ids[(i + 1) % ids.len()])).sum()
+        };
+        let current = cost(&cycle);
+        for start in 0..9 {
+            for len in 1..=3 {
+                let mut rotated = cycle.clone();
+                rotated.rotate_left(start);
+                let (segment, rest) = rotated.split_at(len);
+                for at in 1..rest.len() {
+                    let flipped: Vec<usize> = segment.iter().rev().copied().collect();
+                    let moved = [&rest[..at], &flipped[..], &rest[at..]].concat();
+                    assert!(cost(&moved) >= current - 1e-4, "{:?} beats {:?}", moved, cycle);
+                }
+            }
+        }
+    }
+
+    // ── Random Tour Tests ──────────────────────────────────────────────────────
+
+    #[test]
+    fn random_tour_visits_all_nodes() {
+        let positions = vec![(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)];
+        let mut tour = Tour::new(positions);
+        tour.random_tour();
+
+        // Should have n+1 nodes in route (n cities + return)
+        assert_eq!(tour.route.len(), 4);
+        assert_eq!(tour.route[0].id, 0);
+        assert_eq!(tour.route[3].id, 0);
+    }
+
+    #[test]
+    fn random_tour_same_seed_same_route() {
+        let positions = vec![(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0), (4.0, 4.0), (5.0, 5.0)];
+        let mut a = Tour::new(positions.clone());
+        let mut b = Tour::new(positions);
+        a.set_seed(42);
+        b.set_seed(42);
+        a.random_tour();
+        b.random_tour();
+        let ids = |t: &Tour| t.route.iter().map(|n| n.id).collect::<Vec<_>>();
+        assert_eq!(ids(&a), ids(&b));
+    }
+
+    #[test]
+    fn create_random_nodes_with_seed_is_reproducible() {
+        let a = Tour::create_random_nodes_with_seed(10, 100.0, 100.0, 7);
+        let b = Tour::create_random_nodes_with_seed(10, 100.0, 100.0, 7);
+        for (na, nb) in a.nodes.iter().zip(b.nodes.iter()) {
+            assert_eq!((na.x, na.y), (nb.x, nb.y));
+        }
+    }
+
+    // ── Edge Case Tests ────────────────────────────────────────────────────────
+
+    #[test]
+    #[should_panic(expected = "Tour requires at least one node")]
+    fn tour_new_empty_positions_panics() {
+        let positions: Vec<(f32, f32)> = vec![];
+        Tour::new(positions);
+    }
+
+    // ── Tour::with_matrix Tests ───────────────────────────────────────────────
+
+    #[test]
+    fn with_matrix_should_create_closed_loop() {
+        let positions = vec![(0.0f32, 0.0), (10.0, 0.0), (5.0, 5.0)];
+        let matrix = vec![
+            vec![0.0f32, 100.0, 150.0],
+            vec![100.0,    0.0, 120.0],
+            vec![150.0,  120.0,   0.0],
+        ];
+        let tour = Tour::with_matrix(positions, matrix).unwrap();
+        assert_eq!(tour.route.first().unwrap().id, tour.route.last().unwrap().id);
+    }
+
+    #[test]
+    fn with_matrix_should_have_n_plus_one_route_length() {
+        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (0.5, 0.5)];
+        let matrix = vec![vec![0.0f32; 3]; 3];
+        let tour = Tour::with_matrix(positions, matrix).unwrap();
+        assert_eq!(tour.route.len(), 4);
+    }
+
+    #[test]
+    fn with_matrix_should_use_provided_distances_not_haversine() {
+        // Nearby points — haversine would give ~0.15 km, matrix gives 999.0
+        let positions = vec![(0.0f32, 0.0), (0.001, 0.001), (0.002, 0.002)];
+        let matrix = vec![
+            vec![  0.0f32, 999.0, 500.0],
+            vec![999.0,      0.0, 300.0],
+            vec![500.0,    300.0,   0.0],
+        ];
+        let mut tour = Tour::with_matrix(positions, matrix).unwrap();
+        tour.distance_matrix(); // should be no-op — already populated
+        assert!((tour.distance.cost(0, 1) - 999.0).abs() < 0.01,
+            "expected 999.0, got {:?}", tour.distance.cost(0, 1));
+    }
+
+    #[test]
+    fn with_matrix_cost_should_sum_matrix_values() {
+        // Route order 0→1→2→0: 100 + 200 + 150 = 450
+        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (0.5, 0.5)];
+        let matrix = vec![
+            vec![  0.0f32, 100.0, 150.0],
+            vec![100.0,      0.0, 200.0],
+            vec![150.0,    200.0,   0.0],
+        ];
+        let mut tour = Tour::with_matrix(positions, matrix).unwrap();
+        tour.calculate_cost();
+        assert!((tour.cost - 450.0).abs() < 0.01, "expected 450.0, got {}", tour.cost);
     }
 
     #[test]
-    fn haversine_santiago_to_valparaiso() {
-        // Santiago (-33.87, -70.72) → Valparaíso (-33.04, -71.63) ≈ 125 km
-        let d = haversine_km(-33.87, -70.72, -33.04, -71.63);
-        assert!((d - 125.0).abs() < 10.0, "expected ~125 km, got {:.1}", d);
+    fn with_matrix_should_handle_asymmetric_distances() {
+        // Road times: 0→1 = 60s, 1→0 = 90s (one-way speed difference)
+        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (0.5, 0.5)];
+        let matrix = vec![
+            vec![ 0.0f32,  60.0, 120.0],
+            vec![90.0,      0.0,  80.0],
+            vec![100.0,    70.0,   0.0],
+        ];
+        let tour = Tour::with_matrix(positions, matrix).unwrap();
+        assert!((tour.distance.cost(0, 1) - 60.0).abs() < 0.01);
+        assert!((tour.distance.cost(1, 0) - 90.0).abs() < 0.01);
+        assert_ne!(tour.distance.cost(0, 1), tour.distance.cost(1, 0),
+            "asymmetric matrix should preserve direction");
     }
 
-    // ── Tour Construction Tests ────────────────────────────────────────────────
+    #[test]
+    fn with_matrix_should_handle_unreachable_pairs_as_max() {
+        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (0.5, 0.5)];
+        let matrix = vec![
+            vec![    0.0f32, f32::MAX, 100.0],
+            vec![f32::MAX,       0.0, 200.0],
+            vec![   100.0,     200.0,   0.0],
+        ];
+        let tour = Tour::with_matrix(positions, matrix).unwrap();
+        assert_eq!(tour.distance.cost(0, 1), f32::MAX);
+    }
 
     #[test]
-    fn tour_new_creates_closed_loop() {
-        // Tour with 3 nodes should have route[0] == route[last]
-        let positions = vec![(0.0, 0.0), (10.0, 0.0), (5.0, 5.0)];
-        let tour = Tour::new(positions);
-        assert_eq!(tour.route.len(), 4, "route should have 4 nodes (3 + 1 closing)");
-        assert_eq!(tour.route[0].id, tour.route[3].id);
+    fn with_matrix_nearest_neighbour_should_visit_all_nodes() {
+        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)];
+        let n = positions.len();
+        let mut matrix = vec![vec![f32::MAX; n]; n];
+        for (i, row) in matrix.iter_mut().enumerate() { row[i] = 0.0; }
+        matrix[0][1] = 10.0; matrix[1][0] = 10.0;
+        matrix[1][2] = 20.0; matrix[2][1] = 20.0;
+        matrix[2][3] = 15.0; matrix[3][2] = 15.0;
+        matrix[0][3] = 50.0; matrix[3][0] = 50.0;
+        matrix[0][2] = 30.0; matrix[2][0] = 30.0;
+        matrix[1][3] = 35.0; matrix[3][1] = 35.0;
+        let mut tour = Tour::with_matrix(positions, matrix).unwrap();
+        tour.nearest_neighbour_tour();
+        assert_eq!(tour.route.len(), n + 1);
+        let mut seen = vec![false; n];
+        for node in &tour.route[..n] { seen[node.id] = true; }
+        assert!(seen.iter().all(|&v| v), "not all nodes visited");
     }
 
     #[test]
-    fn tour_new_initializes_nodes() {
-        let positions = vec![(1.0, 2.0), (3.0, 4.0)];
-        let tour = Tour::new(positions);
-        assert_eq!(tour.nodes.len(), 2);
-        assert_eq!(tour.nodes[0].x, 1.0);
-        assert_eq!(tour.nodes[0].y, 2.0);
+    fn with_matrix_two_opt_should_not_worsen_cost() {
+        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let n = positions.len();
+        let mut matrix = vec![vec![0.0f32; n]; n];
+        matrix[0][1] = 10.0; matrix[1][0] = 10.0;
+        matrix[1][2] = 10.0; matrix[2][1] = 10.0;
+        matrix[2][3] = 10.0; matrix[3][2] = 10.0;
+        matrix[3][0] = 10.0; matrix[0][3] = 10.0;
+        matrix[0][2] = 50.0; matrix[2][0] = 50.0;
+        matrix[1][3] = 50.0; matrix[3][1] = 50.0;
+        let mut tour = Tour::with_matrix(positions, matrix).unwrap();
+        tour.nearest_neighbour_tour();
+        tour.calculate_cost();
+        let cost_before = tour.cost;
+        tour.two_opt();
+        tour.calculate_cost();
+        assert!(tour.cost <= cost_before + 0.01,
+            "two_opt worsened cost: {} → {}", cost_before, tour.cost);
+    }
+
+    /// One-way ring: travelling i → i+1 costs 1, going back costs 100 and every other
+    /// pair costs 0.5. Reversing any segment of the ring is very expensive.
+    fn one_way_ring_matrix(n: usize) -> Vec<Vec<f32>> {
+        let mut matrix = vec![vec![0.5f32; n]; n];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            row[i] = 0.0;
+            row[(i + 1) % n] = 1.0;
+            row[(i + n - 1) % n] = 100.0;
+        }
+        matrix
     }
 
     #[test]
-    fn tour_calculate_cost_single_point() {
-        let positions = vec![(0.0, 0.0)];
+    fn two_opt_should_count_reversed_segment_on_asymmetric_matrix() {
+        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0), (4.0, 0.0)];
+        let mut tour = Tour::with_matrix(positions, one_way_ring_matrix(5)).unwrap();
+        tour.calculate_cost();
+        let cost_before = tour.cost;
+        tour.two_opt();
+        tour.calculate_cost();
+        assert!(tour.cost <= cost_before + 0.01,
+            "two_opt worsened asymmetric cost: {} → {}", cost_before, tour.cost);
+    }
+
+    #[test]
+    fn or_opt_should_not_worsen_asymmetric_cost() {
+        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0), (4.0, 0.0), (5.0, 0.0)];
+        let mut tour = Tour::with_matrix(positions, one_way_ring_matrix(6)).unwrap();
+        tour.random_tour();
+        tour.calculate_cost();
+        let cost_before = tour.cost;
+        tour.or_opt();
+        tour.calculate_cost();
+        assert!(tour.cost <= cost_before + 0.01,
+            "or_opt worsened asymmetric cost: {} → {}", cost_before, tour.cost);
+    }
+
+    #[test]
+    fn lin_kernighan_should_count_reversed_segment_on_asymmetric_matrix() {
+        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0), (4.0, 0.0), (5.0, 0.0)];
+        let mut tour = Tour::with_matrix(positions, one_way_ring_matrix(6)).unwrap();
+        tour.random_tour();
+        tour.calculate_cost();
+        let cost_before = tour.cost;
+        tour.lin_kernighan();
+        tour.calculate_cost();
+        assert!(tour.cost <= cost_before + 0.01,
+            "lin_kernighan worsened asymmetric cost: {} → {}", cost_before, tour.cost);
+    }
+
+    // ── Lin-Kernighan Tests ───────────────────────────────────────────────────
+
+    #[test]
+    fn lin_kernighan_does_not_worsen_cost() {
+        let positions = vec![
+            (0.0, 0.0), (0.01, 0.03), (0.02, 0.01), (0.03, 0.04), (0.04, 0.0),
+            (0.05, 0.02), (0.01, 0.05), (0.03, 0.02), (0.05, 0.05), (0.0, 0.02),
+        ];
         let mut tour = Tour::new(positions);
+        tour.random_tour();
         tour.calculate_cost();
-        assert!(tour.cost < 0.01, "single node cost should be ~0, got {}", tour.cost);
+        let cost_before = tour.cost;
+        tour.lin_kernighan();
+        tour.calculate_cost();
+        assert!(tour.cost <= cost_before + 1e-4, "lin_kernighan made route worse: {} → {}", cost_before, tour.cost);
     }
 
     #[test]
-    fn tour_calculate_cost_triangle() {
-        // 3 nodes in a right triangle: (0,0) → (3,0) → (0,4) → (0,0)
-        // Distances: 3 + 5 + 4 = 12 (Haversine approximates Euclidean for small distances)
-        let positions = vec![(0.0, 0.0), (0.003, 0.0), (0.0, 0.004)];
+    fn lin_kernighan_keeps_closed_loop_from_depot() {
+        let positions = vec![(0.0, 0.0), (0.02, 0.01), (0.01, 0.03), (0.04, 0.02), (0.03, 0.0), (0.0, 0.04)];
+        let mut tour = Tour::new(positions);
+        tour.random_tour();
+        tour.lin_kernighan();
+        assert_eq!(tour.route.len(), 7);
+        assert_eq!(tour.route[0].id, 0);
+        assert_eq!(tour.route[6].id, 0);
+        let mut seen = [false; 6];
+        for node in &tour.route[..6] { seen[node.id] = true; }
+        assert!(seen.iter().all(|&v| v), "not all nodes visited");
+    }
+
+    #[test]
+    fn lin_kernighan_untangles_points_on_a_circle() {
+        // Points on a circle: the optimal tour visits them in angular order
+        let n = 12;
+        let positions: Vec<(f32, f32)> = (0..n)
+            .map(|i| {
+                let a = i as f32 * std::f32::consts::TAU / n as f32;
+                (0.05 * a.cos(), 0.05 * a.sin())
+            })
+            .collect();
+        let mut optimal = Tour::new(positions.clone());
+        optimal.calculate_cost();
         let mut tour = Tour::new(positions);
+        tour.random_tour();
+        tour.lin_kernighan();
+        tour.calculate_cost();
+        assert!((tour.cost - optimal.cost).abs() < 1e-3, "expected {}, got {}", optimal.cost, tour.cost);
+    }
+
+    // ── Open Path Tests ───────────────────────────────────────────────────────
+
+    /// Stops along a line, given out of order so the solver has to sort them.
+    fn line_positions() -> Vec<(f32, f32)> {
+        vec![(0.0, 0.0), (0.0, 0.03), (0.0, 0.01), (0.0, 0.04), (0.0, 0.02)]
+    }
+
+    #[test]
+    fn open_path_should_start_and_end_at_fixed_stops() {
+        let mut tour = Tour::new(line_positions());
+        tour.set_endpoints(Some(2), Some(3));
+        tour.nearest_neighbour_tour();
+        tour.two_opt();
+        tour.or_opt();
+        tour.lin_kernighan();
+        assert_eq!(tour.route.len(), 5, "open path should not repeat the start");
+        assert_eq!(tour.route[0].id, 2);
+        assert_eq!(tour.route[4].id, 3);
+        assert!(!tour.is_closed());
+    }
+
+    #[test]
+    fn open_path_with_free_end_should_follow_the_line() {
+        let mut tour = Tour::new(line_positions());
+        tour.set_endpoints(Some(0), None);
+        tour.random_tour();
+        tour.two_opt();
+        tour.or_opt();
+        let ids: Vec<usize> = tour.route.iter().map(|n| n.id).collect();
+        assert_eq!(ids, vec![0, 2, 4, 1, 3]);
+    }
+
+    #[test]
+    fn open_path_with_free_ends_should_visit_every_stop_once() {
+        let mut tour = Tour::new(line_positions());
+        tour.set_endpoints(None, None);
+        tour.random_tour();
+        tour.lin_kernighan();
+        tour.two_opt();
+        tour.calculate_cost();
+        let mut ids: Vec<usize> = tour.route.iter().map(|n| n.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![0, 1, 2, 3, 4]);
+        // Line is 0.04° of longitude at the equator ≈ 4.45 km end to end
+        assert!((tour.cost - 4.45).abs() < 0.05, "expected ~4.45 km, got {}", tour.cost);
+    }
+
+    #[test]
+    fn open_path_should_not_cost_more_than_closed_loop() {
+        let positions = vec![(0.0, 0.0), (0.02, 0.01), (0.01, 0.03), (0.04, 0.02), (0.03, 0.0)];
+        let mut closed = Tour::new(positions.clone());
+        closed.nearest_neighbour_tour();
+        closed.two_opt();
+        closed.calculate_cost();
+        let mut open = Tour::new(positions);
+        open.set_endpoints(Some(0), None);
+        open.nearest_neighbour_tour();
+        open.two_opt();
+        open.calculate_cost();
+        assert!(open.cost <= closed.cost, "open {} > closed {}", open.cost, closed.cost);
+    }
+
+    #[test]
+    fn set_endpoints_same_start_and_end_is_closed_loop() {
+        let mut tour = Tour::new(line_positions());
+        tour.set_endpoints(Some(3), Some(3));
+        tour.nearest_neighbour_tour();
+        assert!(tour.is_closed());
+        assert_eq!(tour.route.len(), 6);
+        assert_eq!(tour.route[0].id, 3);
+        assert_eq!(tour.route[5].id, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Route endpoints must be valid node ids")]
+    fn set_endpoints_should_panic_on_out_of_range_id() {
+        let mut tour = Tour::new(line_positions());
+        tour.set_endpoints(Some(9), None);
+    }
+
+    // ── Exact Solver Tests ────────────────────────────────────────────────────
+
+    /// Deterministic pseudo-random asymmetric matrix.
+    fn scrambled_matrix(n: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n).map(|i| (0..n).map(|j| if i == j { 0.0 } else { rng.gen_range(1.0..100.0) }).collect()).collect()
+    }
+
+    /// Cheapest closed tour from node 0 by trying every permutation.
+    fn brute_force_cost(matrix: &[Vec<f32>]) -> f32 {
+        brute_force_cost_where(matrix, |_| true)
+    }
+
+    /// Cheapest closed route from node 0 among the visiting orders `keep` accepts.
+    fn brute_force_cost_where(matrix: &[Vec<f32>], keep: impl Fn(&[usize]) -> bool) -> f32 {
+        fn visit(
+            matrix: &[Vec<f32>],
+            path: &mut Vec<usize>,
+            left: &mut Vec<usize>,
+            so_far: f32,
+            best: &mut f32,
+            keep: &dyn Fn(&[usize]) -> bool,
+        ) {
+            let last = *path.last().unwrap();
+            if left.is_empty() {
+                if keep(path) {
+                    *best = best.min(so_far + matrix[last][0]);
+                }
+                return;
+            }
+            for i in 0..left.len() {
+                let v = left.remove(i);
+                path.push(v);
+                visit(matrix, path, left, so_far + matrix[last][v], best, keep);
+                path.pop();
+                left.insert(i, v);
+            }
+        }
+        let mut best = f32::MAX;
+        visit(matrix, &mut vec![0], &mut (1..matrix.len()).collect(), 0.0, &mut best, &keep);
+        best
+    }
+
+    fn positions_for(n: usize) -> Vec<(f32, f32)> {
+        (0..n).map(|i| (i as f32, 0.0)).collect()
+    }
+
+    #[test]
+    fn held_karp_should_match_brute_force_on_asymmetric_matrix() {
+        let matrix = scrambled_matrix(8, 5);
+        let expected = brute_force_cost(&matrix);
+        let mut tour = Tour::with_matrix(positions_for(8), matrix).unwrap();
+        assert!(tour.solve_exact());
+        tour.calculate_cost();
+        assert!((tour.cost - expected).abs() < 1e-3, "expected {}, got {}", expected, tour.cost);
+        assert_eq!(tour.route[0].id, 0);
+    }
+
+    #[test]
+    fn branch_and_bound_should_match_brute_force() {
+        let matrix = scrambled_matrix(9, 8);
+        let expected = brute_force_cost(&matrix);
+        let mut tour = Tour::with_matrix(positions_for(9), matrix).unwrap();
+        tour.nearest_neighbour_tour();
+        assert!(tour.branch_and_bound());
+        tour.calculate_cost();
+        assert!((tour.cost - expected).abs() < 1e-3, "expected {}, got {}", expected, tour.cost);
+    }
+
+    #[test]
+    fn branch_and_bound_should_match_held_karp() {
+        for seed in 0..3 {
+            let mut tour = Tour::create_random_nodes_with_seed(HELD_KARP_MAX_NODES, 1.0, 1.0, seed);
+            tour.nearest_neighbour_tour();
+            let mut exact = tour.clone();
+            exact.held_karp();
+            exact.calculate_cost();
+            assert!(tour.branch_and_bound());
+            tour.calculate_cost();
+            assert!((tour.cost - exact.cost).abs() < 1e-4, "seed {}: {} vs {}", seed, tour.cost, exact.cost);
+        }
+    }
+
+    #[test]
+    fn branch_and_bound_should_prove_the_largest_instances() {
+        // Within the expansion limit only thanks to the penalised 1-tree bound
+        let mut tour = Tour::create_random_nodes_with_seed(BRANCH_AND_BOUND_MAX_NODES, 1.0, 1.0, 0);
+        tour.solve(&Strategy::named("fast").unwrap());
+        assert!(tour.branch_and_bound());
+    }
+
+    #[test]
+    fn solve_exact_should_keep_open_path_endpoints() {
+        let mut tour = Tour::new(line_positions());
+        tour.set_endpoints(Some(1), Some(4));
+        assert!(tour.solve_exact());
+        let ids: Vec<usize> = tour.route.iter().map(|n| n.id).collect();
+        assert_eq!((ids[0], ids[4]), (1, 4));
+        // Going out to 3 first and then sweeping down through 2 and 0 is optimal
+        let best: f32 = [1, 3, 0, 2, 4].windows(2).map(|w| tour.travel_cost(w[0], w[1])).sum();
+        let found: f32 = ids.windows(2).map(|w| tour.travel_cost(w[0], w[1])).sum();
+        assert!((found - best).abs() < 1e-3, "expected {}, got {}", best, found);
+    }
+
+    #[test]
+    fn solve_exact_should_skip_large_instances() {
+        let mut tour = Tour::create_random_nodes_with_seed(BRANCH_AND_BOUND_MAX_NODES + 1, 1.0, 1.0, 2);
+        assert!(!tour.solve_exact());
+    }
+
+    // ── Lower Bound Tests ─────────────────────────────────────────────────────
+
+    #[test]
+    fn lower_bound_should_not_exceed_optimal_cost() {
+        for seed in 0..4 {
+            let mut tour = Tour::create_random_nodes_with_seed(12, 1.0, 1.0, seed);
+            tour.solve_exact();
+            tour.calculate_cost();
+            let bound = tour.lower_bound().unwrap();
+            assert!(bound <= tour.cost + 1e-3, "bound {} above optimum {}", bound, tour.cost);
+            assert!(bound >= 0.9 * tour.cost, "bound {} too loose for optimum {}", bound, tour.cost);
+        }
+    }
+
+    #[test]
+    fn lower_bound_should_be_valid_for_asymmetric_matrix() {
+        let matrix = scrambled_matrix(9, 3);
+        let optimum = brute_force_cost(&matrix);
+        let mut tour = Tour::with_matrix(positions_for(9), matrix).unwrap();
+        tour.nearest_neighbour_tour();
+        assert!(tour.lower_bound().unwrap() <= optimum + 1e-3);
+    }
+
+    #[test]
+    fn lower_bound_should_be_tight_on_a_line() {
+        let mut tour = Tour::new(line_positions());
+        tour.solve_exact();
+        tour.calculate_cost();
+        assert!((tour.lower_bound().unwrap() - tour.cost).abs() < 1e-2);
+    }
+
+    #[test]
+    fn lower_bound_should_hold_for_open_paths() {
+        let mut tour = Tour::new(line_positions());
+        tour.set_endpoints(Some(1), Some(4));
+        tour.solve_exact();
+        tour.calculate_cost();
+        let bound = tour.lower_bound().unwrap();
+        assert!(bound <= tour.cost + 1e-3 && bound > 0.0);
+    }
+
+    #[test]
+    fn lower_bound_should_skip_large_instances() {
+        let mut tour = Tour::create_random_nodes_with_seed(LOWER_BOUND_MAX_NODES + 1, 1.0, 1.0, 1);
+        assert_eq!(tour.lower_bound(), None);
+        assert!(tour.distance.lazy(), "no dense matrix is built");
+    }
+
+    // ── Strategy Tests ────────────────────────────────────────────────────────
+
+    #[test]
+    fn strategy_named_should_know_every_preset() {
+        for name in Strategy::NAMES {
+            assert!(Strategy::named(name).is_some(), "missing preset {}", name);
+        }
+        assert!(Strategy::named("slowest").is_none());
+    }
+
+    #[test]
+    fn solve_should_visit_every_node_with_each_preset() {
+        let positions = vec![(0.0, 0.0), (0.02, 0.01), (0.01, 0.03), (0.04, 0.02), (0.03, 0.0), (0.0, 0.04)];
+        for name in Strategy::NAMES {
+            let mut tour = Tour::new(positions.clone());
+            let mut strategy = Strategy::named(name).unwrap();
+            // Keep the metaheuristics short; this test only checks the route stays whole
+            strategy.tune(Some(1_000), None);
+            tour.solve(&strategy);
+            let mut ids: Vec<usize> = tour.route[..6].iter().map(|n| n.id).collect();
+            ids.sort();
+            assert_eq!(ids, vec![0, 1, 2, 3, 4, 5], "{} lost nodes", name);
+            assert!(tour.cost > 0.0);
+        }
+    }
+
+    #[test]
+    fn best_strategy_should_not_cost_more_than_fast() {
+        let mut fast = Tour::create_random_nodes_with_seed(40, 0.5, 0.5, 11);
+        let mut best = Tour::create_random_nodes_with_seed(40, 0.5, 0.5, 11);
+        fast.solve(&Strategy::named("fast").unwrap());
+        best.solve(&Strategy::named("best").unwrap());
+        assert!(best.cost <= fast.cost + 1e-4, "best {} > fast {}", best.cost, fast.cost);
+    }
+
+    // ── Metaheuristic Tests ───────────────────────────────────────────────────
+
+    #[test]
+    fn simulated_annealing_should_not_worsen_two_opt_result() {
+        let mut tour = Tour::create_random_nodes_with_seed(60, 0.5, 0.5, 21);
+        tour.nearest_neighbour_tour();
+        tour.two_opt();
+        tour.calculate_cost();
+        let after_two_opt = tour.cost;
+        tour.simulated_annealing(&Annealing { iterations: 20_000, ..Default::default() });
+        tour.calculate_cost();
+        assert!(tour.cost <= after_two_opt + 1e-4, "{} > {}", tour.cost, after_two_opt);
+        assert_eq!(tour.route.len(), 61);
+    }
+
+    #[test]
+    fn simulated_annealing_should_handle_asymmetric_costs() {
+        let matrix = scrambled_matrix(9, 4);
+        let optimum = brute_force_cost(&matrix);
+        let mut tour = Tour::with_matrix(positions_for(9), matrix).unwrap();
+        tour.set_seed(2);
+        tour.random_tour();
+        tour.simulated_annealing(&Annealing { iterations: 100_000, ..Default::default() });
+        tour.calculate_cost();
+        // Annealing this long on 9 stops lands on or very near the optimum
+        assert!(tour.cost <= optimum * 1.05, "{} vs optimum {}", tour.cost, optimum);
+    }
+
+    #[test]
+    fn simulated_annealing_should_be_reproducible_with_seed() {
+        let run = || {
+            let mut tour = Tour::create_random_nodes_with_seed(30, 0.5, 0.5, 9);
+            tour.set_seed(17);
+            tour.nearest_neighbour_tour();
+            tour.simulated_annealing(&Annealing { iterations: 5_000, ..Default::default() });
+            route_ids(&tour)
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn iterated_local_search_should_not_worsen_two_opt_result() {
+        let mut tour = Tour::create_random_nodes_with_seed(50, 0.5, 0.5, 3);
+        tour.nearest_neighbour_tour();
+        tour.two_opt();
+        tour.or_opt();
         tour.calculate_cost();
-        // Cost should be positive (sum of edge weights)
-        assert!(tour.cost > 0.0, "cost should be positive, got {}", tour.cost);
+        let before = tour.cost;
+        tour.iterated_local_search(30);
+        tour.calculate_cost();
+        assert!(tour.cost <= before + 1e-4, "{} > {}", tour.cost, before);
     }
 
     #[test]
-    fn tour_distance_matrix_symmetric() {
-        let positions = vec![(0.0, 0.0), (10.0, 0.0), (5.0, 5.0)];
-        let mut tour = Tour::new(positions);
-        tour.distance_matrix();
-
-        // Distance[i][j] should equal distance[j][i]
-        for i in 0..tour.nodes.len() {
-            for j in 0..tour.nodes.len() {
-                let d_ij = tour.distance[i][j];
-                let d_ji = tour.distance[j][i];
-                assert_eq!(d_ij, d_ji, "asymmetric distance: [{},{}]={:?} vs [{},{}]={:?}", i, j, d_ij, j, i, d_ji);
-            }
-        }
+    fn double_bridge_should_keep_depot_first_and_every_node() {
+        let mut tour = Tour::create_random_nodes_with_seed(10, 0.5, 0.5, 1);
+        let order: Vec<usize> = (0..10).collect();
+        let kicked = tour.double_bridge(&order);
+        assert_eq!(kicked[0], 0);
+        assert_ne!(kicked, order);
+        let mut sorted = kicked.clone();
+        sorted.sort();
+        assert_eq!(sorted, order);
     }
 
     #[test]
-    fn tour_distance_matrix_diagonal_zero() {
-        let positions = vec![(0.0, 0.0), (10.0, 0.0), (5.0, 5.0)];
-        let mut tour = Tour::new(positions);
-        tour.distance_matrix();
+    fn tune_should_override_metaheuristic_settings() {
+        let mut strategy = Strategy::named("annealing").unwrap();
+        strategy.tune(Some(50), Some(0.8));
+        assert!(strategy.improvements.contains(&Improvement::Annealing(Annealing {
+            iterations: 50,
+            initial_temperature: 0.8,
+            final_temperature: Annealing::default().final_temperature,
+        })));
+        let mut ils = Strategy::named("ils").unwrap();
+        ils.tune(Some(7), None);
+        assert!(ils.improvements.contains(&Improvement::IteratedLocalSearch { kicks: 7 }));
+        let mut genetic = Strategy::named("genetic").unwrap();
+        genetic.tune(Some(12), None);
+        assert!(genetic.improvements.iter().any(|i| matches!(i, Improvement::Genetic(g) if g.generations == 12)));
+        let mut ants = Strategy::named("ants").unwrap();
+        ants.tune(Some(5), None);
+        assert!(ants.improvements.contains(&Improvement::AntColony(AntColony { iterations: 5, ants: 10 })));
+    }
 
-        // Distance from a node to itself should be ~0
-        for i in 0..tour.nodes.len() {
-            let d = tour.distance[i][i].unwrap_or(f32::NAN);
-            assert!(d < 0.01, "diagonal distance should be ~0, got {}", d);
+    #[test]
+    fn ant_colony_should_not_worsen_two_opt_result() {
+        for seed in 0..3 {
+            let mut tour = Tour::create_random_nodes_with_seed(50, 0.5, 0.5, seed);
+            tour.nearest_neighbour_tour();
+            tour.two_opt();
+            tour.calculate_cost();
+            let before = tour.cost;
+            tour.ant_colony(&AntColony { iterations: 20, ants: 5 });
+            tour.calculate_cost();
+            assert!(tour.cost <= before + 1e-4, "seed {}: {} > {}", seed, tour.cost, before);
+            assert_eq!(tour.route.len(), 51);
         }
     }
 
-    // ── Nearest Neighbour Algorithm Tests ──────────────────────────────────────
+    #[test]
+    fn ant_colony_should_find_small_asymmetric_optimum() {
+        let matrix = scrambled_matrix(9, 7);
+        let optimum = brute_force_cost(&matrix);
+        let mut tour = Tour::with_matrix(positions_for(9), matrix).unwrap();
+        tour.set_seed(3);
+        tour.random_tour();
+        tour.ant_colony(&AntColony { iterations: 50, ants: 10 });
+        tour.calculate_cost();
+        assert!(tour.cost <= optimum * 1.05, "{} vs optimum {}", tour.cost, optimum);
+    }
 
     #[test]
-    fn nearest_neighbour_visits_all_nodes() {
-        let positions = vec![(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)];
-        let mut tour = Tour::new(positions);
+    fn ant_colony_should_keep_free_endpoints_open() {
+        let mut tour = Tour::create_random_nodes_with_seed(30, 0.5, 0.5, 8);
+        tour.set_endpoints(None, None);
+        tour.set_seed(4);
         tour.nearest_neighbour_tour();
+        tour.ant_colony(&AntColony { iterations: 10, ants: 5 });
+        let mut ids = route_ids(&tour);
+        assert_eq!(ids.len(), 30);
+        ids.sort();
+        assert_eq!(ids, (0..30).collect::<Vec<_>>());
+    }
 
-        // Route should have n+1 nodes (n cities + 1 return to start)
-        assert_eq!(tour.route.len(), 5, "route should visit all 4 nodes + return");
+    #[test]
+    fn ant_colony_should_be_reproducible_with_seed() {
+        let run = || {
+            let mut tour = Tour::create_random_nodes_with_seed(30, 0.5, 0.5, 9);
+            tour.set_seed(5);
+            tour.nearest_neighbour_tour();
+            tour.ant_colony(&AntColony { iterations: 10, ants: 4 });
+            route_ids(&tour)
+        };
+        assert_eq!(run(), run());
+    }
 
-        // Should start and end at node 0
-        assert_eq!(tour.route[0].id, 0);
-        assert_eq!(tour.route[4].id, 0);
+    // ── Multi-Start Tests ─────────────────────────────────────────────────────
 
-        // Should visit all intermediate nodes once
-        let mut visited = vec![false; tour.nodes.len()];
-        for i in 0..tour.route.len() - 1 {
-            visited[tour.route[i].id] = true;
-        }
-        for v in visited.iter() {
-            assert!(v, "not all nodes visited");
-        }
+    #[test]
+    fn multi_start_should_not_cost_more_than_single_start() {
+        let mut single = Tour::create_random_nodes_with_seed(60, 0.5, 0.5, 13);
+        let mut multi = Tour::create_random_nodes_with_seed(60, 0.5, 0.5, 13);
+        let strategy = Strategy::named("fast").unwrap();
+        single.solve(&strategy);
+        multi.solve_multi_start(&strategy, 8, 4);
+        assert!(multi.cost <= single.cost + 1e-4, "multi {} > single {}", multi.cost, single.cost);
+        let mut ids = route_ids(&multi)[..60].to_vec();
+        ids.sort();
+        assert_eq!(ids, (0..60).collect::<Vec<_>>());
     }
 
     #[test]
-    fn nearest_neighbour_start_is_node_zero() {
-        let positions = vec![(0.0, 0.0), (100.0, 100.0), (10.0, 10.0)];
-        let mut tour = Tour::new(positions);
-        tour.nearest_neighbour_tour();
-        assert_eq!(tour.route[0].id, 0, "should start from node 0");
+    fn multi_start_should_not_depend_on_thread_count() {
+        let run = |threads| {
+            let mut tour = Tour::create_random_nodes_with_seed(40, 0.5, 0.5, 6);
+            tour.set_seed(99);
+            tour.solve_multi_start(&Strategy::named("fast").unwrap(), 50, threads);
+            route_ids(&tour)
+        };
+        assert_eq!(run(1), run(3));
     }
 
     #[test]
-    fn nearest_neighbour_three_nodes() {
-        let positions = vec![(0.0, 0.0), (1.0, 0.0), (0.5, 0.5)];
-        let mut tour = Tour::new(positions);
-        tour.nearest_neighbour_tour();
+    fn multi_start_should_share_the_distance_matrix() {
+        let mut tour = Tour::create_random_nodes_with_seed(10, 0.5, 0.5, 1);
+        tour.distance_matrix();
+        let copy = tour.clone();
+        assert!(Arc::ptr_eq(&tour.distance, &copy.distance));
+        tour.distance_matrix();
+        assert!(Arc::ptr_eq(&tour.distance, &copy.distance));
+    }
 
-        // Should have visited all 3 nodes + return to start
-        assert_eq!(tour.route.len(), 4);
-        assert_eq!(tour.route[0].id, 0);
-        assert_eq!(tour.route[3].id, 0);
+    // ── Budget Tests ──────────────────────────────────────────────────────────
 
-        // All nodes should be present in route[0..3]
-        // (order depends on which neighbor is nearest from each node)
-        let visited: Vec<usize> = tour.route[1..3].iter().map(|n| n.id).collect();
-        assert!(visited.contains(&1));
-        assert!(visited.contains(&2));
+    fn route_ids(tour: &Tour) -> Vec<usize> {
+        tour.route.iter().map(|n| n.id).collect()
     }
 
-    // ── 2-Opt Algorithm Tests ──────────────────────────────────────────────────
-
     #[test]
-    fn two_opt_does_not_worsen_cost() {
-        let positions = vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
+    fn expired_budget_should_leave_route_untouched() {
+        let positions = vec![(0.0, 0.0), (0.02, 0.01), (0.01, 0.03), (0.04, 0.02), (0.03, 0.0), (0.0, 0.04)];
         let mut tour = Tour::new(positions);
+        tour.set_seed(1);
         tour.random_tour();
-        tour.calculate_cost();
-        let cost_before = tour.cost;
-
+        let before = route_ids(&tour);
+        tour.set_budget(Budget::until(Instant::now()));
         tour.two_opt();
-        tour.calculate_cost();
-        let cost_after = tour.cost;
+        tour.or_opt();
+        tour.lin_kernighan();
+        assert_eq!(route_ids(&tour), before);
+    }
 
-        assert!(cost_after <= cost_before + 0.1, "2-opt made route worse: {} → {}", cost_before, cost_after);
+    #[test]
+    fn cancel_flag_should_stop_improvement_loops() {
+        let positions = vec![(0.0, 0.0), (0.02, 0.01), (0.01, 0.03), (0.04, 0.02), (0.03, 0.0), (0.0, 0.04)];
+        let mut tour = Tour::new(positions);
+        tour.set_seed(1);
+        tour.random_tour();
+        let before = route_ids(&tour);
+        let flag = Arc::new(AtomicBool::new(false));
+        tour.set_budget(Budget::default().with_cancel(Arc::clone(&flag)));
+        assert!(!tour.budget().exhausted());
+        flag.store(true, Ordering::Relaxed);
+        assert!(tour.budget().exhausted());
+        tour.two_opt();
+        assert_eq!(route_ids(&tour), before);
     }
 
     #[test]
-    fn two_opt_preserves_all_nodes() {
+    fn future_deadline_should_let_loops_finish() {
         let positions = vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
         let mut tour = Tour::new(positions);
+        tour.set_budget(Budget::until(Instant::now() + std::time::Duration::from_secs(60)));
         tour.random_tour();
         tour.two_opt();
-
-        // All nodes should still be in route (except closing duplicate)
-        assert_eq!(tour.route.len(), 5);
-        assert_eq!(tour.route[0].id, tour.route[4].id);
+        tour.calculate_cost();
+        let mut reference = Tour::new(vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]);
+        reference.calculate_cost();
+        assert!((tour.cost - reference.cost).abs() < 0.1, "expected {}, got {}", reference.cost, tour.cost);
     }
 
-    // ── Or-Opt Algorithm Tests ────────────────────────────────────────────────
+    // ── Subtour Tests ─────────────────────────────────────────────────────────
 
     #[test]
-    fn or_opt_preserves_route_length() {
-        let positions = vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (5.0, 5.0)];
+    fn subtour_should_keep_parent_distances_and_order() {
+        let positions = vec![(0.0, 0.0), (0.01, 0.0), (0.02, 0.0), (0.03, 0.0)];
         let mut tour = Tour::new(positions);
-        tour.random_tour();
-        let len_before = tour.route.len();
+        tour.distance_matrix();
+        let sub = tour.subtour(&[3, 1]);
+        assert_eq!(sub.nodes.len(), 2);
+        assert_eq!(sub.nodes[0].x, 0.03);
+        assert!((sub.travel_cost(0, 1) - tour.travel_cost(3, 1)).abs() < 1e-6);
+    }
 
-        tour.or_opt();
+    // ── Time Window Tests ─────────────────────────────────────────────────────
 
-        assert_eq!(tour.route.len(), len_before, "or_opt changed route length");
+    /// Four stops where every trip takes 100 s.
+    fn uniform_time_tour() -> Tour {
+        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)];
+        let mut matrix = vec![vec![100.0f32; 4]; 4];
+        for (i, row) in matrix.iter_mut().enumerate() { row[i] = 0.0; }
+        Tour::with_matrix(positions, matrix).unwrap()
     }
 
     #[test]
-    fn or_opt_keeps_all_nodes() {
-        let positions = vec![(0.0, 0.0), (1.0, 1.0), (2.0, 0.0), (1.0, -1.0)];
-        let mut tour = Tour::new(positions);
-        tour.random_tour();
-
-        // Record original nodes
-        let original_ids: Vec<usize> = tour.route[..tour.route.len() - 1]
-            .iter()
-            .map(|n| n.id)
-            .collect();
-
-        tour.or_opt();
+    fn schedule_should_wait_for_window_and_add_service() {
+        let mut tour = uniform_time_tour();
+        let windows = vec![None, Some(TimeWindow { earliest: 500.0, latest: 900.0 }), None, None];
+        tour.set_time_windows(windows, vec![0.0, 60.0, 30.0, 0.0], 1000.0);
+        let schedule = tour.schedule();
+        // Route 0 → 1 → 2 → 3 → 0 leaving at 1000 s
+        assert_eq!(schedule.len(), 5);
+        assert!((schedule[1].arrival - 1100.0).abs() < 0.01);
+        assert!((schedule[1].late - 200.0).abs() < 0.01, "expected 200 s late, got {}", schedule[1].late);
+        assert!((schedule[1].departure - 1160.0).abs() < 0.01);
+        assert!((schedule[2].departure - 1290.0).abs() < 0.01);
+        assert!(!tour.meets_time_windows());
+    }
 
-        // Check all nodes still present (minus the closing duplicate)
-        let final_ids: Vec<usize> = tour.route[..tour.route.len() - 1]
-            .iter()
-            .map(|n| n.id)
-            .collect();
+    #[test]
+    fn schedule_should_wait_when_arriving_early() {
+        let mut tour = uniform_time_tour();
+        let windows = vec![None, Some(TimeWindow { earliest: 500.0, latest: 900.0 }), None, None];
+        tour.set_time_windows(windows, vec![0.0; 4], 0.0);
+        let schedule = tour.schedule();
+        assert!((schedule[1].wait - 400.0).abs() < 0.01);
+        assert!((schedule[1].departure - 500.0).abs() < 0.01);
+        assert!(tour.meets_time_windows());
+    }
 
-        assert_eq!(final_ids.len(), original_ids.len());
-        for id in original_ids.iter() {
-            assert!(final_ids.contains(id), "node {} missing after or_opt", id);
-        }
+    #[test]
+    fn time_window_search_should_meet_windows_that_force_the_order() {
+        let mut tour = uniform_time_tour();
+        // Only 0 → 3 → 1 → 2 reaches every stop in time
+        let windows = vec![
+            None,
+            Some(TimeWindow { earliest: 0.0, latest: 200.0 }),
+            Some(TimeWindow { earliest: 0.0, latest: 300.0 }),
+            Some(TimeWindow { earliest: 0.0, latest: 100.0 }),
+        ];
+        tour.set_time_windows(windows, vec![0.0; 4], 0.0);
+        tour.nearest_neighbour_tour();
+        tour.time_window_search();
+        let ids: Vec<usize> = tour.route.iter().map(|n| n.id).collect();
+        assert_eq!(ids, vec![0, 3, 1, 2, 0]);
+        assert!(tour.meets_time_windows());
     }
 
     #[test]
-    fn or_opt_does_not_worsen_cost() {
-        let positions = vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (5.0, 5.0)];
-        let mut tour = Tour::new(positions);
-        tour.random_tour();
-        tour.calculate_cost();
-        let cost_before = tour.cost;
+    fn time_window_search_should_respect_open_path_endpoints() {
+        let mut tour = uniform_time_tour();
+        tour.set_endpoints(Some(0), Some(1));
+        let windows = vec![None, None, Some(TimeWindow { earliest: 0.0, latest: 100.0 }), None];
+        tour.set_time_windows(windows, vec![0.0; 4], 0.0);
+        tour.time_window_search();
+        let ids: Vec<usize> = tour.route.iter().map(|n| n.id).collect();
+        assert_eq!(ids, vec![0, 2, 3, 1]);
+    }
 
-        tour.or_opt();
-        tour.calculate_cost();
-        let cost_after = tour.cost;
+    // ── Order Constraint Tests ────────────────────────────────────────────────
 
-        assert!(cost_after <= cost_before + 0.1, "or_opt made route worse: {} → {}", cost_before, cost_after);
+    /// Whether every `(before, after)` pair comes in that order along the route.
+    fn in_order(tour: &Tour, pairs: &[(usize, usize)]) -> bool {
+        let ids = route_ids(tour);
+        let at = |id: usize| ids.iter().position(|&x| x == id).unwrap();
+        pairs.iter().all(|&(a, b)| at(a) < at(b))
     }
 
-    // ── Random Tour Tests ──────────────────────────────────────────────────────
+    fn constrain(tour: &mut Tour, precedence: &[(usize, usize)], pins: &[(usize, usize)]) {
+        let constraints = OrderConstraints::new(tour.nodes.len(), tour.start, tour.end, precedence, pins).unwrap();
+        tour.set_order_constraints(constraints);
+    }
 
     #[test]
-    fn random_tour_visits_all_nodes() {
-        let positions = vec![(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)];
-        let mut tour = Tour::new(positions);
-        tour.random_tour();
+    fn check_precedence_should_report_the_cycle() {
+        let pairs = [(1, 2), (2, 3), (3, 1), (4, 5)];
+        let err = check_precedence(&pairs, Some(0), Some(0)).unwrap_err();
+        assert_eq!(err, OrderError::Cycle(vec![2, 3, 1]));
+        assert_eq!(err.to_string(), "Precedence constraints form a cycle: 2 → 3 → 1 → 2.");
+        assert_eq!(check_precedence(&[(6, 6)], None, None), Err(OrderError::Cycle(vec![6])));
+    }
 
-        // Should have n+1 nodes in route (n cities + return)
-        assert_eq!(tour.route.len(), 4);
-        assert_eq!(tour.route[0].id, 0);
-        assert_eq!(tour.route[3].id, 0);
+    #[test]
+    fn check_precedence_should_reject_conflicts_with_endpoints() {
+        assert_eq!(check_precedence(&[(2, 0)], Some(0), Some(4)),
+            Err(OrderError::BeforeStart { stop: 2, start: 0 }));
+        assert_eq!(check_precedence(&[(4, 2)], Some(0), Some(4)),
+            Err(OrderError::AfterEnd { stop: 2, end: 4 }));
+        assert_eq!(check_precedence(&[(2, 0)], Some(0), Some(0)),
+            Err(OrderError::BeforeStart { stop: 2, start: 0 }));
+        // Pairs every route meets are dropped
+        assert_eq!(check_precedence(&[(0, 2), (2, 4), (1, 3)], Some(0), Some(4)), Ok(vec![(1, 3)]));
+        assert_eq!(check_precedence(&[(0, 2)], Some(0), Some(0)), Ok(vec![]));
     }
 
-    // ── Edge Case Tests ────────────────────────────────────────────────────────
+    #[test]
+    fn repair_order_should_delay_only_waiting_stops() {
+        let mut tour = Tour::new(positions_for(6));
+        constrain(&mut tour, &[(4, 2)], &[]);
+        tour.set_order(vec![0, 1, 2, 3, 4, 5]);
+        tour.repair_order();
+        assert_eq!(route_ids(&tour), vec![0, 1, 3, 4, 2, 5, 0]);
+    }
 
     #[test]
-    #[should_panic(expected = "Tour requires at least one node")]
-    fn tour_new_empty_positions_panics() {
-        let positions: Vec<(f32, f32)> = vec![];
-        Tour::new(positions);
+    fn repair_order_should_make_way_for_pins() {
+        let mut tour = Tour::new(positions_for(7));
+        // 5 must be second, so 3 has to move ahead of it, and 6 is pinned last
+        constrain(&mut tour, &[(3, 5)], &[(5, 2), (6, 6)]);
+        tour.set_order(vec![0, 6, 1, 2, 3, 4, 5]);
+        tour.repair_order();
+        assert_eq!(route_ids(&tour), vec![0, 3, 5, 1, 2, 4, 6, 0]);
     }
 
-    // ── Tour::with_matrix Tests ───────────────────────────────────────────────
+    #[test]
+    fn order_constraints_should_reject_unreachable_pins() {
+        let check = |pairs: &[(usize, usize)], pins: &[(usize, usize)]| OrderConstraints::new(6, Some(0), Some(0), pairs, pins);
+        assert_eq!(check(&[], &[(0, 1)]), Err(OrderError::PinnedEndpoint(0)));
+        assert_eq!(check(&[], &[(1, 6)]), Err(OrderError::PinOutOfRange { stop: 1, position: 6, stops: 5 }));
+        assert_eq!(check(&[], &[(1, 2), (3, 2)]), Err(OrderError::PinClash { stop: 3, position: 2 }));
+        assert_eq!(check(&[], &[(1, 2), (1, 3)]), Err(OrderError::PinClash { stop: 1, position: 3 }));
+        assert_eq!(check(&[(2, 3)], &[(3, 1)]), Err(OrderError::PinConflict { stop: 3, position: 1 }));
+        // Two stops must precede a stop pinned second
+        assert_eq!(check(&[(1, 3), (2, 3)], &[(3, 2)]), Err(OrderError::PinConflict { stop: 3, position: 2 }));
+        // Something must follow 4, which leaves no stop free for position 5 ahead of the pin
+        assert_eq!(check(&[(4, 5)], &[(4, 5)]), Err(OrderError::PinConflict { stop: 4, position: 5 }));
+        assert!(check(&[(1, 3), (2, 3)], &[(3, 3), (1, 1), (1, 1)]).is_ok());
+    }
 
     #[test]
-    fn with_matrix_should_create_closed_loop() {
-        let positions = vec![(0.0f32, 0.0), (10.0, 0.0), (5.0, 5.0)];
-        let matrix = vec![
-            vec![0.0f32, 100.0, 150.0],
-            vec![100.0,    0.0, 120.0],
-            vec![150.0,  120.0,   0.0],
-        ];
-        let tour = Tour::with_matrix(positions, matrix);
-        assert_eq!(tour.route.first().unwrap().id, tour.route.last().unwrap().id);
+    fn nearest_neighbour_should_meet_order_constraints() {
+        let mut tour = Tour::create_random_nodes_with_seed(30, 0.5, 0.5, 3);
+        let pairs = [(7, 2), (2, 19), (11, 4)];
+        let pins = [(12, 1), (25, 15), (4, 29)];
+        constrain(&mut tour, &pairs, &pins);
+        tour.nearest_neighbour_tour();
+        assert!(in_order(&tour, &pairs));
+        let ids = route_ids(&tour);
+        assert!(pins.iter().all(|&(id, position)| ids[position] == id), "{:?}", ids);
     }
 
     #[test]
-    fn with_matrix_should_have_n_plus_one_route_length() {
-        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (0.5, 0.5)];
-        let matrix = vec![vec![0.0f32; 3]; 3];
-        let tour = Tour::with_matrix(positions, matrix);
-        assert_eq!(tour.route.len(), 4);
+    fn local_search_should_keep_pinned_positions() {
+        let mut tour = Tour::create_random_nodes_with_seed(40, 0.5, 0.5, 14);
+        tour.set_endpoints(Some(0), None);
+        let pins = [(9, 1), (30, 2), (17, 20)];
+        // A fixed-order group is a chain of pairs
+        let group = [(5, 22), (22, 13)];
+        constrain(&mut tour, &group, &pins);
+        tour.solve(&Strategy::named("balanced").unwrap());
+        let ids = route_ids(&tour);
+        assert!(pins.iter().all(|&(id, position)| ids[position] == id), "{:?}", ids);
+        assert!(in_order(&tour, &group));
+        assert_eq!(ids.len(), 40);
     }
 
     #[test]
-    fn with_matrix_should_use_provided_distances_not_haversine() {
-        // Nearby points — haversine would give ~0.15 km, matrix gives 999.0
-        let positions = vec![(0.0f32, 0.0), (0.001, 0.001), (0.002, 0.002)];
-        let matrix = vec![
-            vec![  0.0f32, 999.0, 500.0],
-            vec![999.0,      0.0, 300.0],
-            vec![500.0,    300.0,   0.0],
-        ];
-        let mut tour = Tour::with_matrix(positions, matrix);
-        tour.distance_matrix(); // should be no-op — already populated
-        assert!((tour.distance[0][1].unwrap() - 999.0).abs() < 0.01,
-            "expected 999.0, got {:?}", tour.distance[0][1]);
+    #[should_panic(expected = "Order constraints were checked for other endpoints")]
+    fn set_order_constraints_should_panic_on_other_endpoints() {
+        let mut tour = Tour::new(positions_for(4));
+        tour.set_endpoints(Some(1), None);
+        tour.set_order_constraints(OrderConstraints::new(4, Some(0), Some(0), &[(2, 3)], &[]).unwrap());
     }
 
     #[test]
-    fn with_matrix_cost_should_sum_matrix_values() {
-        // Route order 0→1→2→0: 100 + 200 + 150 = 450
-        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (0.5, 0.5)];
-        let matrix = vec![
-            vec![  0.0f32, 100.0, 150.0],
-            vec![100.0,      0.0, 200.0],
-            vec![150.0,    200.0,   0.0],
-        ];
-        let mut tour = Tour::with_matrix(positions, matrix);
-        tour.calculate_cost();
-        assert!((tour.cost - 450.0).abs() < 0.01, "expected 450.0, got {}", tour.cost);
+    fn every_preset_should_keep_pickups_before_deliveries() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let mut stops: Vec<usize> = (1..40).collect();
+        stops.shuffle(&mut rng);
+        let pairs: Vec<(usize, usize)> = stops.chunks(2).take(12).map(|p| (p[0], p[1])).collect();
+        for name in Strategy::NAMES {
+            let mut tour = Tour::create_random_nodes_with_seed(40, 0.5, 0.5, 2);
+            constrain(&mut tour, &pairs, &[]);
+            let mut strategy = Strategy::named(name).unwrap();
+            strategy.tune(Some(20), None);
+            tour.solve(&strategy);
+            assert!(in_order(&tour, &pairs), "{} broke a precedence pair", name);
+            assert_eq!(tour.route.len(), 41);
+        }
     }
 
     #[test]
-    fn with_matrix_should_handle_asymmetric_distances() {
-        // Road times: 0→1 = 60s, 1→0 = 90s (one-way speed difference)
-        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (0.5, 0.5)];
-        let matrix = vec![
-            vec![ 0.0f32,  60.0, 120.0],
-            vec![90.0,      0.0,  80.0],
-            vec![100.0,    70.0,   0.0],
-        ];
-        let tour = Tour::with_matrix(positions, matrix);
-        assert!((tour.distance[0][1].unwrap() - 60.0).abs() < 0.01);
-        assert!((tour.distance[1][0].unwrap() - 90.0).abs() < 0.01);
-        assert_ne!(tour.distance[0][1], tour.distance[1][0],
-            "asymmetric matrix should preserve direction");
+    fn local_search_should_find_best_order_meeting_precedence() {
+        let matrix = scrambled_matrix(8, 12);
+        let pairs = [(5, 1), (3, 2), (7, 6)];
+        let best = brute_force_cost_where(&matrix, |ids| {
+            let at = |id: usize| ids.iter().position(|&x| x == id).unwrap();
+            pairs.iter().all(|&(a, b)| at(a) < at(b))
+        });
+        let mut found = f32::MAX;
+        for seed in 0..5 {
+            let mut tour = Tour::with_matrix(positions_for(8), matrix.clone()).unwrap();
+            constrain(&mut tour, &pairs, &[]);
+            tour.set_seed(seed);
+            tour.solve(&Strategy::named("ils").unwrap());
+            assert!(in_order(&tour, &pairs));
+            found = found.min(tour.cost);
+        }
+        assert!(found <= best + 1e-3, "{} vs constrained optimum {}", found, best);
     }
 
     #[test]
-    fn with_matrix_should_handle_unreachable_pairs_as_max() {
-        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (0.5, 0.5)];
-        let matrix = vec![
-            vec![    0.0f32, f32::MAX, 100.0],
-            vec![f32::MAX,       0.0, 200.0],
-            vec![   100.0,     200.0,   0.0],
-        ];
-        let tour = Tour::with_matrix(positions, matrix);
-        assert_eq!(tour.distance[0][1], Some(f32::MAX));
+    fn three_opt_and_lin_kernighan_should_check_each_move_against_order_constraints() {
+        let pairs = [(3, 40), (12, 7), (25, 33), (18, 2), (44, 9)];
+        let pins = [(21, 10)];
+        for improvement in [Improvement::ThreeOpt, Improvement::LinKernighan] {
+            let mut tour = Tour::create_random_nodes_with_seed(50, 0.5, 0.5, 9);
+            constrain(&mut tour, &pairs, &pins);
+            tour.random_tour();
+            tour.repair_order();
+            tour.calculate_cost();
+            let start = tour.cost;
+            tour.improve(&improvement);
+            tour.calculate_cost();
+            assert!(in_order(&tour, &pairs) && route_ids(&tour)[10] == 21, "{:?}", improvement);
+            assert!(tour.cost < start / 2.0, "{:?} only got from {} to {}", improvement, start, tour.cost);
+        }
     }
 
     #[test]
-    fn with_matrix_nearest_neighbour_should_visit_all_nodes() {
-        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)];
-        let n = positions.len();
-        let mut matrix = vec![vec![f32::MAX; n]; n];
-        for i in 0..n { matrix[i][i] = 0.0; }
-        matrix[0][1] = 10.0; matrix[1][0] = 10.0;
-        matrix[1][2] = 20.0; matrix[2][1] = 20.0;
-        matrix[2][3] = 15.0; matrix[3][2] = 15.0;
-        matrix[0][3] = 50.0; matrix[3][0] = 50.0;
-        matrix[0][2] = 30.0; matrix[2][0] = 30.0;
-        matrix[1][3] = 35.0; matrix[3][1] = 35.0;
-        let mut tour = Tour::with_matrix(positions, matrix);
-        tour.nearest_neighbour_tour();
-        assert_eq!(tour.route.len(), n + 1);
-        let mut seen = vec![false; n];
-        for node in &tour.route[..n] { seen[node.id] = true; }
-        assert!(seen.iter().all(|&v| v), "not all nodes visited");
+    fn iterated_local_search_should_improve_under_order_constraints() {
+        // Most stops in one chain, so almost every kick breaks it
+        let pairs: Vec<(usize, usize)> = (1..45).map(|id| (id, id + 1)).collect();
+        let pins = [(50, 20)];
+        let constrained = || {
+            let mut tour = Tour::create_random_nodes_with_seed(60, 0.5, 0.5, 5);
+            constrain(&mut tour, &pairs, &pins);
+            tour.set_seed(8);
+            tour.nearest_neighbour_tour();
+            tour
+        };
+        let mut local = constrained();
+        local.iterated_local_search(0);
+        local.calculate_cost();
+        let mut kicked = constrained();
+        kicked.iterated_local_search(10);
+        kicked.calculate_cost();
+        assert!(in_order(&kicked, &pairs));
+        assert!(pins.iter().all(|&(id, at)| route_ids(&kicked)[at] == id));
+        assert!(kicked.cost < local.cost - 1e-4, "kicks found nothing: {} vs {}", kicked.cost, local.cost);
     }
 
     #[test]
-    fn with_matrix_two_opt_should_not_worsen_cost() {
-        let positions = vec![(0.0f32, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
-        let n = positions.len();
-        let mut matrix = vec![vec![0.0f32; n]; n];
-        matrix[0][1] = 10.0; matrix[1][0] = 10.0;
-        matrix[1][2] = 10.0; matrix[2][1] = 10.0;
-        matrix[2][3] = 10.0; matrix[3][2] = 10.0;
-        matrix[3][0] = 10.0; matrix[0][3] = 10.0;
-        matrix[0][2] = 50.0; matrix[2][0] = 50.0;
-        matrix[1][3] = 50.0; matrix[3][1] = 50.0;
-        let mut tour = Tour::with_matrix(positions, matrix);
-        tour.nearest_neighbour_tour();
-        tour.calculate_cost();
-        let cost_before = tour.cost;
-        tour.two_opt();
-        tour.calculate_cost();
-        assert!(tour.cost <= cost_before + 0.01,
-            "two_opt worsened cost: {} → {}", cost_before, tour.cost);
+    fn solve_exact_should_leave_constrained_routes_alone() {
+        let mut tour = Tour::new(line_positions());
+        constrain(&mut tour, &[(3, 2)], &[]);
+        tour.solve(&Strategy::named("fast").unwrap());
+        let route = route_ids(&tour);
+        assert!(!tour.solve_exact());
+        assert_eq!(route_ids(&tour), route);
+        assert!(in_order(&tour, &[(3, 2)]));
     }
 
     #[test]
     #[should_panic(expected = "Tour requires at least one node")]
     fn with_matrix_should_panic_on_empty_positions() {
-        Tour::with_matrix(vec![], vec![]);
+        let _ = Tour::with_matrix(vec![], vec![]);
+    }
+
+    #[test]
+    fn with_matrix_should_reject_ragged_matrix() {
+        let err = Tour::with_matrix(positions_for(2), vec![vec![0.0, 1.0], vec![1.0]]).unwrap_err();
+        assert_eq!(err, MatrixError::NotSquare);
+        assert_eq!(err.to_string(), "Distance matrix must be square.");
+    }
+
+    #[test]
+    fn with_matrix_should_reject_a_matrix_of_another_size() {
+        let err = Tour::with_matrix(positions_for(3), vec![vec![0.0, 1.0], vec![1.0, 0.0]]).unwrap_err();
+        assert_eq!(err, MatrixError::SizeMismatch { rows: 2, positions: 3 });
+        assert_eq!(err.to_string(), "Distance matrix has 2 rows for 3 positions.");
+    }
+
+    #[test]
+    fn distance_matrix_should_cache_haversine_costs() {
+        let mut tour = Tour::new(line_positions());
+        assert!(tour.distance.lazy());
+        let before = tour.travel_cost(1, 3);
+        tour.distance_matrix();
+        assert!(!tour.distance.lazy());
+        assert_eq!(tour.travel_cost(1, 3), before);
     }
 }
\ No newline at end of file
@@ -0,0 +1,358 @@
+use crate::tour::{Tour, EPS};
+use std::fmt;
+
+/// Longest segment moved between two routes by cross-exchange.
+const MAX_EXCHANGE_LEN: usize = 3;
+/// Rounds of inter-route moves followed by per-route local search.
+const MAX_ROUNDS: usize = 20;
+
+/// Vehicles available to serve the stops, all with the same capacity.
+#[derive(Debug, Clone)]
+pub struct Fleet {
+    pub vehicles: usize,
+    pub capacity: f32,
+}
+
+/// Route driven by one vehicle, from the depot back to the depot. Idle vehicles have no stops.
+#[derive(Debug, Clone)]
+pub struct VehicleRoute {
+    pub stops: Vec<usize>,
+    pub load: f32,
+    pub cost: f32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum VrpError {
+    /// The stop's demand alone exceeds a vehicle's capacity.
+    StopExceedsCapacity(usize),
+    /// The stops could not be packed into the available vehicles.
+    FleetTooSmall,
+}
+
+impl fmt::Display for VrpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VrpError::StopExceedsCapacity(id) => write!(f, "Stop {} has more demand than a vehicle can carry.", id),
+            VrpError::FleetTooSmall => write!(f, "The stops do not fit in the available vehicles."),
+        }
+    }
+}
+
+/// Capacitated vehicle routing: splits every node of `tour` except `depot` across the
+/// fleet so no vehicle carries more than its capacity, minimising the summed route cost.
+///
+/// Routes are built with Clarke-Wright savings, then improved with cross-exchange moves
+/// between routes (which include relocating and swapping stops) alternated with 2-opt and
+/// Or-opt inside each route, using the tour's own distances. Stops improving when the
+/// tour's budget runs out.
+pub fn solve(tour: &mut Tour, depot: usize, demand: &[f32], fleet: &Fleet) -> Result<Vec<VehicleRoute>, VrpError> {
+    assert_eq!(demand.len(), tour.nodes.len(), "Demand must have one entry per node");
+    tour.distance_matrix();
+    let customers: Vec<usize> = (0..tour.nodes.len()).filter(|&id| id != depot).collect();
+    if let Some(&id) = customers.iter().find(|&&id| demand[id] > fleet.capacity) {
+        return Err(VrpError::StopExceedsCapacity(id));
+    }
+
+    let mut plan = Plan { tour, depot, demand, capacity: fleet.capacity, routes: Vec::new() };
+    plan.savings(&customers);
+    if plan.routes.len() > fleet.vehicles {
+        plan.merge_down_to(fleet.vehicles);
+    }
+    if plan.routes.len() > fleet.vehicles && !plan.worst_fit_decreasing(&customers, fleet.vehicles) {
+        return Err(VrpError::FleetTooSmall);
+    }
+
+    plan.optimise_each_route();
+    for _ in 0..MAX_ROUNDS {
+        if plan.tour.budget().exhausted() || !plan.cross_exchange() {
+            break;
+        }
+        plan.optimise_each_route();
+    }
+
+    let mut routes: Vec<VehicleRoute> = plan.routes.iter()
+        .map(|r| VehicleRoute {
+            stops: std::iter::once(depot).chain(r.iter().copied()).chain(std::iter::once(depot)).collect(),
+            load: plan.load(r),
+            cost: plan.route_cost(r) as f32,
+        })
+        .collect();
+    routes.resize(fleet.vehicles.max(routes.len()), VehicleRoute { stops: Vec::new(), load: 0.0, cost: 0.0 });
+    Ok(routes)
+}
+
+/// Working state: every route lists its customers only, the depot is implicit at both ends.
+struct Plan<'a> {
+    tour: &'a Tour,
+    depot: usize,
+    demand: &'a [f32],
+    capacity: f32,
+    routes: Vec<Vec<usize>>,
+}
+
+impl Plan<'_> {
+    fn c(&self, from: usize, to: usize) -> f64 {
+        self.tour.travel_cost(from, to) as f64
+    }
+
+    fn load(&self, route: &[usize]) -> f32 {
+        route.iter().map(|&id| self.demand[id]).sum()
+    }
+
+    fn route_cost(&self, route: &[usize]) -> f64 {
+        let Some((&first, &last)) = route.first().zip(route.last()) else { return 0.0 };
+        self.c(self.depot, first)
+            + route.windows(2).map(|w| self.c(w[0], w[1])).sum::<f64>()
+            + self.c(last, self.depot)
+    }
+
+    /// Clarke-Wright savings: start with one route per stop and join the end of one route
+    /// to the start of another while that saves the most and the load still fits.
+    fn savings(&mut self, customers: &[usize]) {
+        let d = self.depot;
+        let mut savings: Vec<(f64, usize, usize)> = Vec::new();
+        for &i in customers {
+            for &j in customers {
+                if i != j {
+                    let s = self.c(i, d) + self.c(d, j) - self.c(i, j);
+                    if s > EPS {
+                        savings.push((s, i, j));
+                    }
+                }
+            }
+        }
+        savings.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut routes: Vec<Option<Vec<usize>>> = customers.iter().map(|&id| Some(vec![id])).collect();
+        let mut route_of = vec![usize::MAX; self.tour.nodes.len()];
+        for (r, &id) in customers.iter().enumerate() {
+            route_of[id] = r;
+        }
+        for (_, i, j) in savings {
+            let (ri, rj) = (route_of[i], route_of[j]);
+            if ri == rj {
+                continue;
+            }
+            let (Some(a), Some(b)) = (&routes[ri], &routes[rj]) else { continue };
+            if a.last() != Some(&i) || b.first() != Some(&j) || self.load(a) + self.load(b) > self.capacity {
+                continue;
+            }
+            let b = routes[rj].take().unwrap_or_default();
+            for &id in &b {
+                route_of[id] = ri;
+            }
+            if let Some(a) = routes[ri].as_mut() {
+                a.extend(b);
+            }
+        }
+        self.routes = routes.into_iter().flatten().collect();
+    }
+
+    /// Joins whole routes, cheapest join first, until at most `vehicles` remain or no
+    /// pair fits in one vehicle.
+    fn merge_down_to(&mut self, vehicles: usize) {
+        while self.routes.len() > vehicles {
+            let mut best: Option<(f64, usize, usize)> = None;
+            for a in 0..self.routes.len() {
+                for b in 0..self.routes.len() {
+                    if a == b || self.load(&self.routes[a]) + self.load(&self.routes[b]) > self.capacity {
+                        continue;
+                    }
+                    let mut joined = self.routes[a].clone();
+                    joined.extend(&self.routes[b]);
+                    let increase = self.route_cost(&joined)
+                        - self.route_cost(&self.routes[a])
+                        - self.route_cost(&self.routes[b]);
+                    if best.is_none_or(|(cost, _, _)| increase < cost) {
+                        best = Some((increase, a, b));
+                    }
+                }
+            }
+            let Some((_, a, b)) = best else { return };
+            let tail = self.routes[b].clone();
+            self.routes[a].extend(tail);
+            self.routes.remove(b);
+        }
+    }
+
+    /// Packs stops into `vehicles` routes by decreasing demand, each into the vehicle with
+    /// the most room left. Returns `false` if some stop does not fit anywhere.
+    fn worst_fit_decreasing(&mut self, customers: &[usize], vehicles: usize) -> bool {
+        let mut sorted = customers.to_vec();
+        sorted.sort_by(|&a, &b| self.demand[b].partial_cmp(&self.demand[a]).unwrap_or(std::cmp::Ordering::Equal));
+        let mut bins: Vec<Vec<usize>> = vec![Vec::new(); vehicles];
+        let mut loads = vec![0.0f32; vehicles];
+        for id in sorted {
+            let Some(bin) = (0..vehicles)
+                .filter(|&v| loads[v] + self.demand[id] <= self.capacity)
+                .min_by(|&x, &y| loads[x].partial_cmp(&loads[y]).unwrap_or(std::cmp::Ordering::Equal))
+            else {
+                return false;
+            };
+            bins[bin].push(id);
+            loads[bin] += self.demand[id];
+        }
+        self.routes = bins.into_iter().filter(|b| !b.is_empty()).collect();
+        true
+    }
+
+    /// Runs 2-opt and Or-opt inside every route, keeping the result only if it is shorter.
+    fn optimise_each_route(&mut self) {
+        for r in 0..self.routes.len() {
+            if self.routes[r].len() < 2 {
+                continue;
+            }
+            let ids: Vec<usize> = std::iter::once(self.depot).chain(self.routes[r].iter().copied()).collect();
+            let mut sub = self.tour.subtour(&ids);
+            sub.two_opt();
+            sub.or_opt();
+            let improved: Vec<usize> = sub.route[1..sub.route.len() - 1].iter().map(|n| ids[n.id]).collect();
+            if self.route_cost(&improved) < self.route_cost(&self.routes[r]) - EPS {
+                self.routes[r] = improved;
+            }
+        }
+    }
+
+    /// Cross-exchange: swaps a segment of up to `MAX_EXCHANGE_LEN` stops of one route with
+    /// a segment of another, keeping their orientation. An empty segment on one side is a
+    /// relocation, two single stops a swap. Applies improving moves until none is left and
+    /// returns whether anything changed.
+    fn cross_exchange(&mut self) -> bool {
+        let mut changed = false;
+        let mut improved = true;
+        while improved && !self.tour.budget().exhausted() {
+            improved = false;
+            'search: for a in 0..self.routes.len() {
+                for b in a + 1..self.routes.len() {
+                    for la in 0..=MAX_EXCHANGE_LEN {
+                        for lb in 0..=MAX_EXCHANGE_LEN {
+                            if la + lb == 0 {
+                                continue;
+                            }
+                            if let Some((i, j)) = self.best_exchange(a, b, la, lb) {
+                                let seg_a: Vec<usize> = self.routes[a].drain(i..i + la).collect();
+                                let seg_b: Vec<usize> = self.routes[b].splice(j..j + lb, seg_a).collect();
+                                self.routes[a].splice(i..i, seg_b);
+                                improved = true;
+                                changed = true;
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self.routes.retain(|r| !r.is_empty());
+        changed
+    }
+
+    /// First improving exchange of `routes[a][i..i+la]` with `routes[b][j..j+lb]`.
+    fn best_exchange(&self, a: usize, b: usize, la: usize, lb: usize) -> Option<(usize, usize)> {
+        let (ra, rb) = (&self.routes[a], &self.routes[b]);
+        if la > ra.len() || lb > rb.len() {
+            return None;
+        }
+        let (load_a, load_b) = (self.load(ra), self.load(rb));
+        for i in 0..=ra.len() - la {
+            let seg_a = &ra[i..i + la];
+            let demand_a = self.load(seg_a);
+            for j in 0..=rb.len() - lb {
+                let seg_b = &rb[j..j + lb];
+                let demand_b = self.load(seg_b);
+                if load_a - demand_a + demand_b > self.capacity || load_b - demand_b + demand_a > self.capacity {
+                    continue;
+                }
+                let delta = self.splice_delta(ra, i, la, seg_b) + self.splice_delta(rb, j, lb, seg_a);
+                if delta < -EPS {
+                    return Some((i, j));
+                }
+            }
+        }
+        None
+    }
+
+    /// Cost change of replacing `route[i..i+len]` with `segment`, counting only the edges
+    /// at the seams — the segments' own edges move with them unchanged.
+    fn splice_delta(&self, route: &[usize], i: usize, len: usize, segment: &[usize]) -> f64 {
+        let prev = if i == 0 { self.depot } else { route[i - 1] };
+        let next = route.get(i + len).copied().unwrap_or(self.depot);
+        let seams = |seg: &[usize]| match (seg.first(), seg.last()) {
+            (Some(&first), Some(&last)) => self.c(prev, first) + self.c(last, next),
+            _ => self.c(prev, next),
+        };
+        seams(segment) - seams(&route[i..i + len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Depot in the middle with two clusters of three stops to the west and east.
+    fn two_clusters() -> Tour {
+        Tour::new(vec![
+            (0.0, 0.0),
+            (0.0, -0.10), (0.01, -0.11), (-0.01, -0.11),
+            (0.0, 0.10), (0.01, 0.11), (-0.01, 0.11),
+        ])
+    }
+
+    fn served(routes: &[VehicleRoute]) -> Vec<usize> {
+        let mut ids: Vec<usize> = routes.iter()
+            .flat_map(|r| r.stops.iter().copied().filter(|&id| id != 0))
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn solve_should_serve_every_stop_once() {
+        let mut tour = two_clusters();
+        let fleet = Fleet { vehicles: 2, capacity: 3.0 };
+        let routes = solve(&mut tour, 0, &[0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0], &fleet).unwrap();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(served(&routes), vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn solve_should_respect_capacity_and_split_clusters() {
+        let mut tour = two_clusters();
+        let fleet = Fleet { vehicles: 2, capacity: 3.0 };
+        let routes = solve(&mut tour, 0, &[0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0], &fleet).unwrap();
+        for r in &routes {
+            assert!(r.load <= 3.0, "route overloaded: {}", r.load);
+            assert_eq!(r.stops.first(), Some(&0));
+            assert_eq!(r.stops.last(), Some(&0));
+            let west = r.stops.iter().filter(|&&id| (1..=3).contains(&id)).count();
+            let east = r.stops.iter().filter(|&&id| (4..=6).contains(&id)).count();
+            assert!(west == 0 || east == 0, "route mixes clusters: {:?}", r.stops);
+        }
+    }
+
+    #[test]
+    fn solve_should_leave_spare_vehicles_idle() {
+        let mut tour = two_clusters();
+        let fleet = Fleet { vehicles: 4, capacity: 10.0 };
+        let routes = solve(&mut tour, 0, &[0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0], &fleet).unwrap();
+        assert_eq!(routes.len(), 4);
+        assert_eq!(served(&routes), vec![1, 2, 3, 4, 5, 6]);
+        let total: f32 = routes.iter().map(|r| r.cost).sum();
+        assert!(total > 0.0);
+    }
+
+    #[test]
+    fn solve_should_reject_stop_larger_than_capacity() {
+        let mut tour = two_clusters();
+        let fleet = Fleet { vehicles: 2, capacity: 3.0 };
+        let result = solve(&mut tour, 0, &[0.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0], &fleet);
+        assert_eq!(result.unwrap_err(), VrpError::StopExceedsCapacity(2));
+    }
+
+    #[test]
+    fn solve_should_reject_fleet_that_is_too_small() {
+        let mut tour = two_clusters();
+        let fleet = Fleet { vehicles: 1, capacity: 3.0 };
+        let result = solve(&mut tour, 0, &[0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0], &fleet);
+        assert_eq!(result.unwrap_err(), VrpError::FleetTooSmall);
+    }
+}